@@ -0,0 +1,40 @@
+// The gRPC service is defined manually instead of being generated from
+// proto/syntaxhighlight/v1/syntaxhighlight.proto, because the messages embed
+// `scip::types::Document` (a rust-protobuf type) rather than prost-generated
+// SCIP types. The message definitions live in src/grpc.rs and must be kept in
+// sync with the .proto file.
+fn main() {
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        tonic_build::manual::Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::{input}"))
+            .output_type(format!("crate::grpc::{output}"))
+            .codec_path("tonic::codec::ProstCodec")
+            .build()
+    };
+
+    let service = tonic_build::manual::Service::builder()
+        .name("SyntaxHighlighter")
+        .package("syntaxhighlight.v1")
+        .method(method(
+            "highlight",
+            "Highlight",
+            "HighlightRequest",
+            "HighlightResponse",
+        ))
+        .method(method(
+            "symbols",
+            "Symbols",
+            "SymbolsRequest",
+            "SymbolsResponse",
+        ))
+        .method(method("ctags", "Ctags", "CtagsRequest", "CtagsResponse"))
+        .build();
+
+    tonic_build::manual::Builder::new()
+        .build_client(false)
+        .compile(&[service]);
+
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,19 @@
+(function_definition) @scope
+(compound_statement) @scope
+(for_statement) @scope
+
+(function_definition
+  declarator: (function_declarator
+                declarator: ((identifier) @definition.function
+                             (#set! "scope" "parent")
+                             (#set! "hoist"))))
+
+;; int a, *b, c[2] = ...; and the same in parameters
+(parameter_declaration declarator: (identifier) @definition.term)
+(declaration declarator: (identifier) @definition.term)
+(init_declarator declarator: (identifier) @definition.term)
+(pointer_declarator declarator: (identifier) @definition.term)
+(array_declarator declarator: (identifier) @definition.term)
+
+;; reference
+(identifier) @reference
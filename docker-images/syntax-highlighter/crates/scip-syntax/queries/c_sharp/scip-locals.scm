@@ -0,0 +1,28 @@
+(declaration_list) @scope
+(block) @scope
+(for_statement) @scope
+(for_each_statement) @scope
+(catch_clause) @scope
+(using_statement) @scope
+(switch_section) @scope
+(method_declaration) @scope
+(constructor_declaration) @scope
+(local_function_statement) @scope
+(lambda_expression) @scope
+
+(parameter name: (identifier) @definition.term)
+(variable_declarator . (identifier) @definition.term)
+(for_each_statement left: (identifier) @definition.term)
+(catch_declaration name: (identifier) @definition.term)
+
+;; x => x
+(lambda_expression . (identifier) @definition.term)
+
+;; if (shape is Circle circle) { ... }
+(declaration_pattern (identifier) @definition.term .)
+
+;; reference
+(identifier) @reference
+
+;; Members aren't locals
+(member_access_expression name: (identifier) @skip)
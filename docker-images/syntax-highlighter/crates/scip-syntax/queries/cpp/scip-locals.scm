@@ -0,0 +1,31 @@
+(function_definition) @scope
+(compound_statement) @scope
+(for_statement) @scope
+(for_range_loop) @scope
+(catch_clause) @scope
+(lambda_expression) @scope
+
+(function_definition
+  declarator: (function_declarator
+                declarator: ((identifier) @definition.function
+                             (#set! "scope" "parent")
+                             (#set! "hoist"))))
+
+;; int a, *b, &c, d[2] = ...; and the same in parameters
+(parameter_declaration declarator: (identifier) @definition.term)
+(optional_parameter_declaration declarator: (identifier) @definition.term)
+(declaration declarator: (identifier) @definition.term)
+(init_declarator declarator: (identifier) @definition.term)
+(pointer_declarator declarator: (identifier) @definition.term)
+(array_declarator declarator: (identifier) @definition.term)
+(reference_declarator (identifier) @definition.term)
+(for_range_loop declarator: (identifier) @definition.term)
+
+;; auto [a, b] = ...
+(structured_binding_declarator (identifier) @definition.term)
+
+;; reference
+(identifier) @reference
+
+;; std::swap isn't a local
+(qualified_identifier name: (identifier) @skip)
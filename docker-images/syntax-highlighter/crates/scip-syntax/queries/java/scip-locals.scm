@@ -0,0 +1,30 @@
+(method_declaration) @scope
+(block) @scope
+(for_statement) @scope
+(enhanced_for_statement) @scope
+(catch_clause) @scope
+(try_with_resources_statement) @scope
+(constructor_declaration) @scope
+(lambda_expression) @scope
+
+(formal_parameter name: (identifier) @definition.term)
+(spread_parameter (variable_declarator name: (identifier) @definition.term))
+(inferred_parameters (identifier) @definition.term)
+(lambda_expression parameters: (identifier) @definition.term)
+(local_variable_declaration
+  declarator: (variable_declarator name: (identifier) @definition.term))
+(enhanced_for_statement name: (identifier) @definition.term)
+(catch_formal_parameter name: (identifier) @definition.term)
+
+;; try (var reader = ...) { ... }
+(resource name: (identifier) @definition.term)
+
+;; if (shape instanceof Circle circle) { ... }
+(instanceof_expression name: (identifier) @definition.term)
+
+;; reference
+(identifier) @reference
+
+;; Fields and methods aren't locals
+(field_access field: (identifier) @skip)
+(method_invocation name: (identifier) @skip)
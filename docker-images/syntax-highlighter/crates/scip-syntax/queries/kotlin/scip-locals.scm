@@ -0,0 +1,44 @@
+(function_declaration) @scope
+(secondary_constructor) @scope
+(anonymous_function) @scope
+(lambda_literal) @scope
+(for_statement) @scope
+(catch_block) @scope
+(when_expression) @scope
+(class_body) @scope
+
+;; Only bodies with braces, `if (a) b` has no scope of its own
+(control_structure_body "{") @scope
+
+(parameter (simple_identifier) @definition.term)
+(parameter_with_optional_type (simple_identifier) @definition.term)
+(catch_block (simple_identifier) @definition.term)
+
+;; Properties of classes and files can be used before they are declared,
+;;   unlike the ones in functions. These have to come before the patterns
+;;   below, which match the same names.
+(class_body
+  (property_declaration
+    (variable_declaration ((simple_identifier) @definition.term (#set! "hoist")))))
+(source_file
+  (property_declaration
+    (variable_declaration ((simple_identifier) @definition.term (#set! "hoist")))))
+
+;; val x = ... and val (a, b) = ..., the value can't see the new names yet
+(property_declaration
+  (variable_declaration (simple_identifier) @definition.term)) @after
+(property_declaration
+  (multi_variable_declaration
+    (variable_declaration (simple_identifier) @definition.term))) @after
+
+;; for (x in ...), { x -> ... } and when (val x = ...)
+;;   The same declarations are used everywhere a name is introduced.
+(variable_declaration (simple_identifier) @definition.term)
+
+;; reference
+(simple_identifier) @reference
+(interpolated_identifier) @reference
+
+;; a.b and f(name = ...) aren't locals
+(navigation_suffix (simple_identifier) @skip)
+(value_argument (simple_identifier) @skip "=")
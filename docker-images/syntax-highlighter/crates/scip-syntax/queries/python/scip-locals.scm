@@ -0,0 +1,44 @@
+(function_definition) @scope
+(class_definition) @scope
+(lambda) @scope
+
+;; Comprehensions have their own scope, unlike for loops
+(list_comprehension) @scope
+(set_comprehension) @scope
+(dictionary_comprehension) @scope
+(generator_expression) @scope
+
+(function_definition
+  name: ((identifier) @definition.function
+         (#set! "scope" "parent")))
+
+(class_definition
+  name: ((identifier) @definition.type
+         (#set! "scope" "parent")))
+
+;; def f(a, b: int, c=1, d: int = 2, *args, **kwargs)
+(parameters (identifier) @definition.term)
+(lambda_parameters (identifier) @definition.term)
+(typed_parameter (identifier) @definition.term)
+(default_parameter name: (identifier) @definition.term)
+(typed_default_parameter name: (identifier) @definition.term)
+(list_splat_pattern (identifier) @definition.term)
+(dictionary_splat_pattern (identifier) @definition.term)
+
+;; Assigning to a name for the first time defines it, the other
+;; assignments in the same scope are references to that definition.
+(assignment left: (identifier) @definition.term)
+(for_statement left: (identifier) @definition.term)
+(for_in_clause left: (identifier) @definition.term)
+(named_expression name: (identifier) @definition.term)
+
+;; a, (b, c) = ...
+(pattern_list (identifier) @definition.term)
+(tuple_pattern (identifier) @definition.term)
+
+;; reference
+(identifier) @reference
+
+;; obj.attribute and f(keyword=...) aren't locals
+(attribute attribute: (identifier) @skip)
+(keyword_argument name: (identifier) @skip)
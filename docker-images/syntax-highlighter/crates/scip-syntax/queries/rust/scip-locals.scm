@@ -0,0 +1,60 @@
+(function_item) @scope
+(closure_expression) @scope
+(for_expression) @scope
+(block) @scope
+(match_arm) @scope
+;; if let and while let bindings are only visible in there.
+(if_expression condition: [(let_condition) (let_chain)]) @scope
+(while_expression condition: [(let_condition) (let_chain)]) @scope
+
+(function_item
+  name: ((identifier) @definition.function
+         (#set! "scope" "parent")
+         (#set! "hoist")))
+
+(parameter pattern: (identifier) @definition.term)
+(closure_parameters (identifier) @definition.term)
+(for_expression pattern: (identifier) @definition.term)
+
+;; Bindings of let, if let and while let are only visible after the whole
+;;   statement or condition, so that in `let (a, b) = (b, a)` and
+;;   `if let Some(x) = x` the right side means the names from before. These
+;;   have to come before the patterns below, which match the same names, and
+;;   go two patterns deep.
+(let_declaration pattern: (identifier) @definition.term) @after
+((let_declaration pattern: (_ (identifier) @definition.term)) @after
+ (#match? @definition.term "^[a-z_]"))
+((let_declaration pattern: (_ (_ (identifier) @definition.term))) @after
+ (#match? @definition.term "^[a-z_]"))
+(let_declaration
+  pattern: (_ (field_pattern name: (shorthand_field_identifier) @definition.term))) @after
+(let_condition pattern: (identifier) @definition.term) @after
+((let_condition pattern: (_ (identifier) @definition.term)) @after
+ (#match? @definition.term "^[a-z_]"))
+((let_condition pattern: (_ (_ (identifier) @definition.term))) @after
+ (#match? @definition.term "^[a-z_]"))
+(let_condition
+  pattern: (_ (field_pattern name: (shorthand_field_identifier) @definition.term))) @after
+
+;; Bindings inside of patterns, wherever the pattern is.
+;;   Paths like `None` are identifiers too, but bindings are lowercase.
+((match_pattern . (identifier) @definition.term)
+ (#match? @definition.term "^[a-z_]"))
+((tuple_struct_pattern type: (_) (identifier) @definition.term)
+ (#match? @definition.term "^[a-z_]"))
+(tuple_pattern (identifier) @definition.term)
+(slice_pattern (identifier) @definition.term)
+(field_pattern name: (shorthand_field_identifier) @definition.term)
+(field_pattern pattern: (identifier) @definition.term)
+(ref_pattern (identifier) @definition.term)
+(mut_pattern (identifier) @definition.term)
+(reference_pattern (identifier) @definition.term)
+(captured_pattern . (identifier) @definition.term)
+
+;; reference
+(identifier) @reference
+
+;; std::mem::swap and println! aren't locals
+(scoped_identifier path: (identifier) @skip)
+(scoped_identifier name: (identifier) @skip)
+(macro_invocation macro: (identifier) @skip)
@@ -0,0 +1,45 @@
+(template_body) @scope
+(function_definition) @scope
+(block) @scope
+(for_expression) @scope
+(case_clause) @scope
+(lambda_expression) @scope
+
+(parameter name: (identifier) @definition.term)
+(lambda_expression . (identifier) @definition.term)
+(binding . (identifier) @definition.term)
+
+;; Members of classes and objects can be used before they are defined,
+;;   unlike the vals and vars in a block. These have to come before the
+;;   patterns below, which match the same names.
+(template_body
+  (val_definition pattern: ((identifier) @definition.term (#set! "hoist"))))
+(template_body
+  (var_definition pattern: ((identifier) @definition.term (#set! "hoist"))))
+(compilation_unit
+  (val_definition pattern: ((identifier) @definition.term (#set! "hoist"))))
+(compilation_unit
+  (var_definition pattern: ((identifier) @definition.term (#set! "hoist"))))
+
+(val_definition pattern: (identifier) @definition.term)
+(var_definition pattern: (identifier) @definition.term)
+
+;; Bindings inside of patterns, wherever the pattern is: in case clauses,
+;; for comprehensions and val definitions.
+;;   Stable identifiers like `Nil` are identifiers too, but bindings are lowercase.
+((case_clause . (identifier) @definition.term)
+ (#match? @definition.term "^[a-z_]"))
+((enumerator . (identifier) @definition.term)
+ (#match? @definition.term "^[a-z_]"))
+((case_class_pattern (identifier) @definition.term)
+ (#match? @definition.term "^[a-z_]"))
+((tuple_pattern (identifier) @definition.term)
+ (#match? @definition.term "^[a-z_]"))
+(typed_pattern . (identifier) @definition.term)
+(capture_pattern . (identifier) @definition.term)
+
+;; reference
+(identifier) @reference
+
+;; a.b isn't a local
+(field_expression field: (identifier) @skip)
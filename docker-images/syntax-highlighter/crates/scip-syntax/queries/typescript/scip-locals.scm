@@ -0,0 +1,47 @@
+(function_declaration) @scope.function
+(generator_function_declaration) @scope.function
+(function) @scope.function
+(generator_function) @scope.function
+(method_definition) @scope.function
+(statement_block) @scope
+(for_statement) @scope
+(for_in_statement) @scope
+(catch_clause) @scope
+(arrow_function) @scope.function
+
+(function_declaration
+  name: ((identifier) @definition.function
+         (#set! "scope" "parent")))
+(generator_function_declaration
+  name: ((identifier) @definition.function
+         (#set! "scope" "parent")))
+(class_declaration name: (type_identifier) @definition.type)
+
+;; var belongs to the function it's in, not to the block. This has to come
+;;   before the pattern below, which matches let and const too.
+(variable_declaration
+  (variable_declarator
+    name: ((identifier) @definition.term
+           (#set! "scope" "function"))))
+(variable_declarator name: (identifier) @definition.term)
+(required_parameter pattern: (identifier) @definition.term)
+(optional_parameter pattern: (identifier) @definition.term)
+(for_in_statement left: (identifier) @definition.term)
+(catch_clause parameter: (identifier) @definition.term)
+
+;; x => x
+(arrow_function parameter: (identifier) @definition.term)
+
+;; Destructuring, wherever the pattern is:
+;;   const { a, b: c, d = 1, ...e } = ...
+;;   const [f, g = 1, ...h] = ...
+(object_pattern (shorthand_property_identifier_pattern) @definition.term)
+(object_assignment_pattern left: (shorthand_property_identifier_pattern) @definition.term)
+(pair_pattern value: (identifier) @definition.term)
+(array_pattern (identifier) @definition.term)
+(assignment_pattern left: (identifier) @definition.term)
+(rest_pattern (identifier) @definition.term)
+
+;; reference
+(identifier) @reference
+(shorthand_property_identifier) @reference
@@ -0,0 +1,34 @@
+(Decl (FnProto)) @scope
+(Block) @scope
+(ForStatement) @scope
+(WhileStatement) @scope
+(IfStatement) @scope
+(SwitchProng) @scope
+
+(Decl
+  (FnProto
+    function: ((IDENTIFIER) @definition.function
+               (#set! "scope" "parent")
+               (#set! "hoist"))))
+
+parameter: (IDENTIFIER) @definition.term
+
+;; Declarations in containers, like the file itself, can be used before they
+;;   are declared, unlike the ones in blocks. This has to come before the
+;;   pattern below, which matches the same names.
+(Decl
+  (VarDecl variable_type_function: ((IDENTIFIER) @definition.term (#set! "hoist"))))
+(VarDecl variable_type_function: (IDENTIFIER) @definition.term)
+
+;; for (items) |item, i|, if (optional) |value|, catch |err|, ...
+(Payload (IDENTIFIER) @definition.term)
+(PtrPayload (IDENTIFIER) @definition.term)
+(PtrIndexPayload (IDENTIFIER) @definition.term)
+
+;; reference
+(IDENTIFIER) @reference
+
+;; Fields aren't locals
+field_access: (IDENTIFIER) @skip
+field_member: (IDENTIFIER) @skip
+field_constant: (IDENTIFIER) @skip
@@ -8,7 +8,7 @@ use std::{
 use anyhow::{Context, Result};
 use itertools::intersperse;
 use scip::types::{descriptor::Suffix, symbol_information, Descriptor};
-use scip_treesitter_languages::parsers::BundledParser;
+use scip_treesitter_languages::detection::detect_language;
 use serde::{Deserialize, Serialize};
 
 use crate::{get_globals, globals::Scope};
@@ -55,8 +55,8 @@ impl<'a> Reply<'a> {
         writer.write_all("\n".as_bytes()).unwrap();
     }
 
-    pub fn write_tag<W: std::io::Write>(
-        writer: &mut W,
+    pub fn emit_tag(
+        emit: &mut dyn FnMut(Reply),
         scope: &Scope,
         path: &'a str,
         language: &'a str,
@@ -78,16 +78,14 @@ impl<'a> Reply<'a> {
         }
         scope_deduplicator.insert(dedup, ());
 
-        let tag = Self::Tag {
+        emit(Self::Tag {
             name,
             path,
             language,
             line: scope.scope_range.start_line as usize + 1,
             kind: descriptors_to_kind(&scope.descriptors, &scope.kind),
             scope: tag_scope,
-        };
-
-        tag.write(writer);
+        });
     }
 }
 
@@ -114,8 +112,8 @@ fn descriptors_to_kind(
     }
 }
 
-fn emit_tags_for_scope<W: std::io::Write>(
-    buf_writer: &mut BufWriter<W>,
+fn emit_tags_for_scope(
+    emit: &mut dyn FnMut(Reply),
     path: &str,
     parent_scopes: Vec<String>,
     scope: &Scope,
@@ -137,8 +135,8 @@ fn emit_tags_for_scope<W: std::io::Write>(
             .then(|| parent_scopes.join("."));
         let tag_scope = tag_scope.as_deref();
 
-        Reply::write_tag(
-            &mut *buf_writer,
+        Reply::emit_tag(
+            &mut *emit,
             scope,
             path,
             language,
@@ -149,7 +147,7 @@ fn emit_tags_for_scope<W: std::io::Write>(
 
     for subscope in &scope.children {
         emit_tags_for_scope(
-            buf_writer,
+            &mut *emit,
             path,
             curr_scopes.clone(),
             subscope,
@@ -168,7 +166,7 @@ fn emit_tags_for_scope<W: std::io::Write>(
                 .map(|d| d.name.clone()),
         );
 
-        Reply::Tag {
+        emit(Reply::Tag {
             name: global.descriptors.last().unwrap().name.clone(),
             path,
             language,
@@ -179,8 +177,7 @@ fn emit_tags_for_scope<W: std::io::Write>(
                 .not()
                 .then(|| scope_name.join("."))
                 .as_deref(),
-        }
-        .write(buf_writer);
+        });
     }
 }
 
@@ -189,11 +186,18 @@ pub fn generate_tags<W: std::io::Write>(
     filename: String,
     file_data: &[u8],
 ) -> Option<()> {
-    let path = path::Path::new(&filename);
-    let extension = path.extension()?.to_str()?;
+    for_each_tag(&filename, file_data, &mut |tag| tag.write(buf_writer))
+}
+
+/// Calls `emit` with a [`Reply::Tag`] for every tag found in the file.
+///
+/// This is what [`generate_tags`] uses under the hood, but it lets callers
+/// collect the tags instead of writing them out as JSON lines.
+pub fn for_each_tag(filename: &str, file_data: &[u8], emit: &mut dyn FnMut(Reply)) -> Option<()> {
+    let path = path::Path::new(filename);
     let filepath = path.file_name()?.to_str()?;
 
-    let parser = BundledParser::get_parser_from_extension(extension)?;
+    let parser = detect_language(filename, file_data)?.parser;
     let (root_scope, _) = match get_globals(&parser, file_data)? {
         Ok(vals) => vals,
         Err(err) => {
@@ -211,7 +215,7 @@ pub fn generate_tags<W: std::io::Write>(
 
     let mut scope_deduplicator = HashMap::new();
     emit_tags_for_scope(
-        buf_writer,
+        emit,
         filepath,
         vec![],
         &root_scope,
@@ -7,6 +7,8 @@ use scip_macros::include_scip_query;
 use scip_treesitter_languages::parsers::BundledParser;
 use tree_sitter::{Language, Parser, Query};
 
+use crate::locals::Visibility;
+
 #[derive(Debug)]
 pub struct Transform {
     pattern: Regex,
@@ -186,8 +188,11 @@ impl TagConfiguration {
 }
 
 pub struct LocalConfiguration {
-    language: Language,
+    pub(crate) language: Language,
     pub query: Query,
+    /// Where definitions are visible, unless their pattern in the query sets
+    /// `hoist`.
+    pub visibility: Visibility,
 }
 
 impl LocalConfiguration {
@@ -266,6 +271,9 @@ mod locals {
 
     macro_rules! create_locals_configuration {
         ($name:tt, $parser:path, $file:tt) => {
+            create_locals_configuration!($name, $parser, $file, Visibility::Hoisted);
+        };
+        ($name:tt, $parser:path, $file:tt, $visibility:expr) => {
             pub fn $name() -> &'static LocalConfiguration {
                 static INSTANCE: OnceCell<LocalConfiguration> = OnceCell::new();
 
@@ -276,6 +284,7 @@ mod locals {
                     LocalConfiguration {
                         language,
                         query: Query::new(language, query).unwrap(),
+                        visibility: $visibility,
                     }
                 })
             }
@@ -284,11 +293,36 @@ mod locals {
 
     create_locals_configuration!(go, BundledParser::Go, "go");
     create_locals_configuration!(perl, BundledParser::Perl, "perl");
+    create_locals_configuration!(rust, BundledParser::Rust, "rust", Visibility::Ordered);
+    create_locals_configuration!(python, BundledParser::Python, "python");
+    create_locals_configuration!(javascript, BundledParser::Javascript, "javascript");
+    create_locals_configuration!(typescript, BundledParser::Typescript, "typescript");
+    // TSX only adds JSX to TypeScript, so it uses the TypeScript locals query.
+    create_locals_configuration!(tsx, BundledParser::Tsx, "typescript");
+    create_locals_configuration!(java, BundledParser::Java, "java", Visibility::Ordered);
+    create_locals_configuration!(kotlin, BundledParser::Kotlin, "kotlin", Visibility::Ordered);
+    create_locals_configuration!(scala, BundledParser::Scala, "scala", Visibility::Ordered);
+    create_locals_configuration!(c_sharp, BundledParser::C_Sharp, "c_sharp");
+    create_locals_configuration!(c, BundledParser::C, "c", Visibility::Ordered);
+    create_locals_configuration!(cpp, BundledParser::Cpp, "cpp", Visibility::Ordered);
+    create_locals_configuration!(zig, BundledParser::Zig, "zig", Visibility::Ordered);
 
     pub fn get_local_configuration(parser: BundledParser) -> Option<&'static LocalConfiguration> {
         match parser {
             BundledParser::Go => Some(go()),
             BundledParser::Perl => Some(perl()),
+            BundledParser::Rust => Some(rust()),
+            BundledParser::Python => Some(python()),
+            BundledParser::Javascript => Some(javascript()),
+            BundledParser::Typescript => Some(typescript()),
+            BundledParser::Tsx => Some(tsx()),
+            BundledParser::Java => Some(java()),
+            BundledParser::Kotlin => Some(kotlin()),
+            BundledParser::Scala => Some(scala()),
+            BundledParser::C_Sharp => Some(c_sharp()),
+            BundledParser::C => Some(c()),
+            BundledParser::Cpp => Some(cpp()),
+            BundledParser::Zig => Some(zig()),
             _ => None,
         }
     }
@@ -296,3 +330,34 @@ mod locals {
 
 pub use locals::get_local_configuration;
 pub use tags::get_tag_configuration;
+
+#[cfg(test)]
+mod test {
+    use scip_treesitter_languages::registry::LANGUAGES;
+
+    use super::*;
+
+    #[test]
+    fn registry_capabilities() {
+        for language in LANGUAGES {
+            let capabilities = language.capabilities;
+            match &language.parser {
+                Some(parser) => {
+                    assert_eq!(
+                        capabilities.tags,
+                        get_tag_configuration(parser).is_some(),
+                        "tags of {}",
+                        language.name
+                    );
+                    assert_eq!(
+                        capabilities.locals,
+                        get_local_configuration(parser.clone()).is_some(),
+                        "locals of {}",
+                        language.name
+                    );
+                }
+                None => assert!(!capabilities.tags && !capabilities.locals),
+            }
+        }
+    }
+}
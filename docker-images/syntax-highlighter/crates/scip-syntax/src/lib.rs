@@ -45,7 +45,7 @@ mod test {
     use scip_treesitter::snapshot::dump_document;
     use scip_treesitter_languages::parsers::BundledParser;
 
-    use crate::ctags::generate_tags;
+    use crate::ctags::{for_each_tag, generate_tags, Reply};
 
     macro_rules! generate_tags_and_snapshot {
         (Scip, $scip_name:tt, $filename:tt) => {
@@ -122,4 +122,28 @@ mod test {
         test_scip_tags_go_constant,
         "go-const.go"
     );
+
+    #[test]
+    fn test_tags_ignore_code_in_text_files() {
+        let mut tags = 0;
+        for_each_tag("notes.txt", b"def foo():\n    pass\n", &mut |_| tags += 1);
+        assert_eq!(tags, 0);
+    }
+
+    #[test]
+    fn test_tags_cpp_extensions() {
+        let source = b"namespace ui {\nclass Widget {};\n}\n";
+        for filename in ["widget.cc", "widget.hpp", "widget.h"] {
+            let mut names = vec![];
+            for_each_tag(filename, source, &mut |reply| {
+                if let Reply::Tag { name, language, .. } = reply {
+                    names.push((name, language.to_string()));
+                }
+            });
+            assert!(
+                names.contains(&("Widget".to_string(), "cpp".to_string())),
+                "{filename}: {names:?}"
+            );
+        }
+    }
 }
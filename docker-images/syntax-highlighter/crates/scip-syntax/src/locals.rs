@@ -1,6 +1,6 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use protobuf::Enum;
-use rustc_hash::FxHashMap as HashMap;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
 use scip::{
     symbol::format_symbol,
     types::{Occurrence, Symbol},
@@ -14,7 +14,11 @@ use crate::languages::LocalConfiguration;
 pub struct Scope<'a> {
     pub scope: Node<'a>,
     pub range: PackedRange,
-    pub definitions: HashMap<&'a str, Definition<'a>>,
+    /// Whether this is the scope of a function, captured as `@scope.function`,
+    /// see [`ScopeModifier::Function`].
+    pub is_function: bool,
+    /// The definitions of each name, in the order they're in the file.
+    pub definitions: HashMap<&'a str, Vec<Definition<'a>>>,
     pub references: HashMap<&'a str, Vec<Reference<'a>>>,
     pub children: Vec<Scope<'a>>,
 }
@@ -44,6 +48,7 @@ impl<'a> Scope<'a> {
         Self {
             scope,
             range: scope.into(),
+            is_function: false,
             definitions: HashMap::default(),
             references: HashMap::default(),
             children: vec![],
@@ -65,7 +70,7 @@ impl<'a> Scope<'a> {
     pub fn insert_definition(&mut self, definition: Definition<'a>) {
         // TODO: Probably should assert that this the root node?
         if definition.scope_modifier == ScopeModifier::Global {
-            self.definitions.insert(definition.identifier, definition);
+            self.push_definition(definition);
             return;
         }
 
@@ -74,44 +79,105 @@ impl<'a> Scope<'a> {
             .iter_mut()
             .find(|child| child.range.contains(&definition.range))
         {
-            child.insert_definition(definition)
+            let belongs_to_child = match definition.scope_modifier {
+                // A parent definition belongs to the scope around the innermost
+                // scope it is in, like the name of a function does.
+                ScopeModifier::Parent => child
+                    .children
+                    .iter()
+                    .any(|grandchild| grandchild.range.contains(&definition.range)),
+                // A function definition skips the scopes in between, like
+                // `var` in a block in JavaScript.
+                ScopeModifier::Function => child.encloses_function(&definition.range),
+                ScopeModifier::Local | ScopeModifier::Global => true,
+            };
+
+            if belongs_to_child {
+                child.insert_definition(definition)
+            } else {
+                self.push_definition(definition);
+            }
         } else {
-            self.definitions.insert(definition.identifier, definition);
+            self.push_definition(definition);
+        }
+    }
+
+    /// Whether this scope or one of its descendants around `range` is the
+    /// scope of a function.
+    fn encloses_function(&self, range: &PackedRange) -> bool {
+        self.is_function
+            || self
+                .children
+                .iter()
+                .any(|child| child.range.contains(range) && child.encloses_function(range))
+    }
+
+    fn push_definition(&mut self, definition: Definition<'a>) {
+        let definitions = self.definitions.entry(definition.identifier).or_default();
+
+        // Only the first hoisted definition of a name counts, like the first
+        // assignment to a variable in Python. The others become references.
+        if definition.visibility == Visibility::Hoisted {
+            match definitions
+                .iter()
+                .position(|d| d.visibility == Visibility::Hoisted)
+            {
+                Some(idx) if definitions[idx].range < definition.range => return,
+                Some(idx) => {
+                    definitions.remove(idx);
+                }
+                None => {}
+            }
         }
+
+        let idx = definitions.partition_point(|d| d.range < definition.range);
+        definitions.insert(idx, definition);
+    }
+
+    /// The definition in this scope that `reference` means, if there is one:
+    /// the last ordered definition before it, or else the hoisted one.
+    fn visible_definition(&self, reference: &Reference<'a>) -> Option<&Definition<'a>> {
+        let definitions = self.definitions.get(reference.identifier)?;
+        let start = (reference.range.start_line, reference.range.start_col);
+
+        definitions
+            .iter()
+            .rev()
+            .find(|d| d.visibility == Visibility::Ordered && d.visible_from <= start)
+            .or_else(|| {
+                definitions
+                    .iter()
+                    .find(|d| d.visibility == Visibility::Hoisted)
+            })
     }
 
     pub fn insert_reference(&mut self, reference: Reference<'a>) {
-        if let Some(definition) = self.definitions.get(&reference.identifier) {
-            if definition.node.id() == reference.node.id() {
+        if let Some(definitions) = self.definitions.get(&reference.identifier) {
+            if definitions
+                .iter()
+                .any(|d| d.node.id() == reference.node.id())
+            {
                 return;
             }
         }
 
-        match self.children.binary_search_by_key(
-            &(reference.range.start_line, reference.range.start_col),
-            |r| (r.range.start_line, r.range.start_col),
-        ) {
-            Ok(_) => {
-                // self.children[idx].insert_reference(reference);
-                todo!("I'm not sure what to do yet, think more now");
+        // Children don't overlap, so the last one that starts at or before the
+        // reference is the only one that can contain it. That includes a child
+        // that starts with the reference, like a match arm with its pattern.
+        let start = (reference.range.start_line, reference.range.start_col);
+        let idx = self
+            .children
+            .partition_point(|child| (child.range.start_line, child.range.start_col) <= start);
+
+        match idx.checked_sub(1).map(|idx| &mut self.children[idx]) {
+            Some(child) if child.range.contains(&reference.range) => {
+                child.insert_reference(reference)
             }
-            Err(idx) => match idx {
-                0 => self
-                    .references
-                    .entry(reference.identifier)
-                    .or_default()
-                    .push(reference),
-                idx => {
-                    if self.children[idx - 1].range.contains(&reference.range) {
-                        self.children[idx - 1].insert_reference(reference)
-                    } else {
-                        self.references
-                            .entry(reference.identifier)
-                            .or_default()
-                            .push(reference)
-                    }
-                }
-            },
+            _ => self
+                .references
+                .entry(reference.identifier)
+                .or_default()
+                .push(reference),
         }
     }
 
@@ -146,15 +212,22 @@ impl<'a> Scope<'a> {
 
     pub fn into_occurrences(&mut self, hint: usize) -> Vec<Occurrence> {
         let mut occs = Vec::with_capacity(hint);
-        self.rec_into_occurrences(&mut 0, &mut occs);
+        let mut symbols = HashMap::default();
+        self.rec_definitions_into_occurrences(&mut 0, &mut symbols, &mut occs);
+        self.rec_references_into_occurrences(&mut vec![], &symbols, &mut occs);
         occs
     }
 
-    fn rec_into_occurrences(&self, id: &mut usize, occurrences: &mut Vec<Occurrence>) {
+    fn rec_definitions_into_occurrences(
+        &self,
+        id: &mut usize,
+        symbols: &mut HashMap<usize, String>,
+        occurrences: &mut Vec<Occurrence>,
+    ) {
         // TODO: I'm a little sad about this.
         //  We could probably make this a runtime option, where `self` has a `sorted` value
         //  that decides whether we need to or not. But on a huge file, this made no difference.
-        let mut values = self.definitions.values().collect::<Vec<_>>();
+        let mut values = self.definitions.values().flatten().collect::<Vec<_>>();
         values.sort_by_key(|d| &d.range);
 
         for definition in values {
@@ -170,42 +243,34 @@ impl<'a> Scope<'a> {
                 // syntax_kind: todo!(),
                 ..Default::default()
             });
-
-            if let Some(references) = self.references.get(definition.identifier) {
-                for reference in references {
-                    occurrences.push(scip::types::Occurrence {
-                        range: reference.node.to_scip_range(),
-                        symbol: symbol.clone(),
-                        ..Default::default()
-                    });
-                }
-            }
-
-            self.children
-                .iter()
-                .for_each(|c| c.occurrences_for_children(definition, symbol.as_str(), occurrences));
+            symbols.insert(definition.node.id(), symbol);
         }
 
         self.children
             .iter()
-            .for_each(|c| c.rec_into_occurrences(id, occurrences));
+            .for_each(|c| c.rec_definitions_into_occurrences(id, symbols, occurrences));
     }
 
-    fn occurrences_for_children(
-        self: &Scope<'a>,
-        def: &Definition<'a>,
-        symbol: &str,
+    fn rec_references_into_occurrences<'s>(
+        &'s self,
+        scopes: &mut Vec<&'s Scope<'a>>,
+        symbols: &HashMap<usize, String>,
         occurrences: &mut Vec<Occurrence>,
     ) {
-        if self.definitions.contains_key(def.identifier) {
-            return;
-        }
+        scopes.push(self);
+
+        for reference in self.references.values().flatten() {
+            // The innermost scope that has a definition for the reference wins
+            let symbol = scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.visible_definition(reference))
+                .and_then(|definition| symbols.get(&definition.node.id()));
 
-        if let Some(references) = self.references.get(def.identifier) {
-            for reference in references {
+            if let Some(symbol) = symbol {
                 occurrences.push(scip::types::Occurrence {
                     range: reference.node.to_scip_range(),
-                    symbol: symbol.to_string(),
+                    symbol: symbol.clone(),
                     ..Default::default()
                 });
             }
@@ -213,7 +278,9 @@ impl<'a> Scope<'a> {
 
         self.children
             .iter()
-            .for_each(|c| c.occurrences_for_children(def, symbol, occurrences));
+            .for_each(|c| c.rec_references_into_occurrences(scopes, symbols, occurrences));
+
+        scopes.pop();
     }
 
     #[allow(dead_code)]
@@ -248,9 +315,23 @@ pub enum ScopeModifier {
     #[default]
     Local,
     Parent,
+    /// The innermost function scope around the definition, or the root.
+    Function,
     Global,
 }
 
+/// Where in its scope a definition can be referenced.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// Everywhere in the scope, also before the definition, like `var` in
+    /// JavaScript or any assignment in Python.
+    #[default]
+    Hoisted,
+    /// Only after the definition, like `let` in Rust. A later definition
+    /// with the same name shadows it.
+    Ordered,
+}
+
 #[derive(Debug)]
 pub struct Definition<'a> {
     pub group: &'a str,
@@ -258,6 +339,9 @@ pub struct Definition<'a> {
     pub node: Node<'a>,
     pub range: PackedRange,
     pub scope_modifier: ScopeModifier,
+    pub visibility: Visibility,
+    /// The line and column from which an ordered definition is visible.
+    pub visible_from: (i32, i32),
 }
 
 #[derive(Debug)]
@@ -282,6 +366,13 @@ pub fn parse_tree<'a>(
     let mut definitions = vec![];
     let mut references = vec![];
 
+    // Nodes that look like references but aren't, like the `b` in `a.b`.
+    let mut skipped = HashSet::default();
+
+    // The index in `definitions` and the pattern of every node that has been
+    // defined, see below.
+    let mut defined = HashMap::<usize, (usize, usize)>::default();
+
     for m in cursor.matches(&config.query, root_node, source_bytes) {
         let mut node = None;
 
@@ -289,6 +380,8 @@ pub fn parse_tree<'a>(
         let mut definition = None;
         let mut reference = None;
         let mut scope_modifier = None;
+        let mut visibility = None;
+        let mut after = None;
 
         for capture in m.captures {
             let capture_name = match capture_names.get(capture.index as usize) {
@@ -296,10 +389,18 @@ pub fn parse_tree<'a>(
                 None => continue,
             };
 
+            // The node that the definition is visible after, not a node of its own.
+            if capture_name == "after" {
+                after = Some(capture.node);
+                continue;
+            }
+
             node = Some(capture.node);
 
             if capture_name.starts_with("definition") {
-                assert!(definition.is_none(), "only one definition per match");
+                if definition.is_some() {
+                    bail!("only one definition per match");
+                }
                 definition = Some(capture_name);
 
                 // Handle scope modifiers
@@ -309,24 +410,39 @@ pub fn parse_tree<'a>(
                         match prop.value.as_deref() {
                             Some("global") => scope_modifier = Some(ScopeModifier::Global),
                             Some("parent") => scope_modifier = Some(ScopeModifier::Parent),
+                            Some("function") => scope_modifier = Some(ScopeModifier::Function),
                             Some("local") => scope_modifier = Some(ScopeModifier::Local),
-                            // TODO: Should probably error instead
-                            Some(other) => panic!("unknown scope-testing: {}", other),
+                            Some(other) => bail!("unknown scope: {}", other),
                             None => {}
                         }
                     }
+
+                    if &(*prop.key) == "hoist" {
+                        match prop.value.as_deref() {
+                            None => visibility = Some(Visibility::Hoisted),
+                            Some(other) => bail!("hoist doesn't take a value: {}", other),
+                        }
+                    }
                 }
             }
 
             if capture_name.starts_with("reference") {
-                assert!(reference.is_none(), "only one reference per match");
+                if reference.is_some() {
+                    bail!("only one reference per match");
+                }
                 reference = Some(capture_name);
             }
 
             if capture_name.starts_with("scope") {
-                assert!(scope.is_none(), "declare only one scope per match");
+                if scope.is_some() {
+                    bail!("declare only one scope per match");
+                }
                 scope = Some(capture);
             }
+
+            if capture_name.starts_with("skip") {
+                skipped.insert(capture.node.id());
+            }
         }
 
         let node = match node {
@@ -341,13 +457,35 @@ pub fn parse_tree<'a>(
             };
 
             let scope_modifier = scope_modifier.unwrap_or_default();
-            definitions.push(Definition {
+            let visibility = visibility.unwrap_or(config.visibility);
+            let visible_from = match after {
+                Some(after) => after.end_position(),
+                None => node.start_position(),
+            };
+            let definition = Definition {
                 range: node.into(),
                 group,
                 identifier,
                 node,
                 scope_modifier,
-            });
+                visibility,
+                visible_from: (visible_from.row as i32, visible_from.column as i32),
+            };
+
+            // A node can match a pattern for a particular context, like a
+            // property of a class, and a more general one. Like in highlight
+            // queries, the pattern that comes first wins.
+            match defined.get(&node.id()) {
+                Some(&(idx, pattern)) if m.pattern_index < pattern => {
+                    definitions[idx] = definition;
+                    defined.insert(node.id(), (idx, m.pattern_index));
+                }
+                Some(_) => {}
+                None => {
+                    defined.insert(node.id(), (definitions.len(), m.pattern_index));
+                    definitions.push(definition);
+                }
+            }
         } else if let Some(group) = reference {
             let identifier = match node.utf8_text(source_bytes) {
                 Ok(identifier) => identifier,
@@ -366,7 +504,9 @@ pub fn parse_tree<'a>(
                 None => continue,
             };
 
-            scopes.push(Scope::new(scope.node));
+            let mut new_scope = Scope::new(scope.node);
+            new_scope.is_function = capture_names[scope.index as usize] == "scope.function";
+            scopes.push(new_scope);
         }
     }
 
@@ -394,6 +534,7 @@ pub fn parse_tree<'a>(
 
     root.clean_empty_scopes();
 
+    references.retain(|reference| !skipped.contains(&reference.node.id()));
     while let Some(m) = references.pop() {
         root.insert_reference(m);
     }
@@ -405,10 +546,12 @@ pub fn parse_tree<'a>(
 
 #[cfg(test)]
 mod test {
+    use std::panic::AssertUnwindSafe;
+
     use anyhow::Result;
     use scip::types::Document;
     use scip_treesitter::snapshot::{dump_document_with_config, EmitSymbol, SnapshotOptions};
-    use scip_treesitter_languages::parsers::BundledParser;
+    use scip_treesitter_languages::{parsers::BundledParser, registry::LANGUAGES};
 
     use super::*;
     use crate::languages::LocalConfiguration;
@@ -492,4 +635,310 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_can_do_rust() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::Rust).unwrap();
+        let source_code = include_str!("../testdata/locals.rs");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_python() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::Python).unwrap();
+        let source_code = include_str!("../testdata/locals.py");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_javascript() -> Result<()> {
+        let mut config =
+            crate::languages::get_local_configuration(BundledParser::Javascript).unwrap();
+        let source_code = include_str!("../testdata/locals.js");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_typescript() -> Result<()> {
+        let mut config =
+            crate::languages::get_local_configuration(BundledParser::Typescript).unwrap();
+        let source_code = include_str!("../testdata/locals.ts");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_tsx() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::Tsx).unwrap();
+        let source_code = include_str!("../testdata/locals.tsx");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_java() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::Java).unwrap();
+        let source_code = include_str!("../testdata/locals.java");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_kotlin() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::Kotlin).unwrap();
+        let source_code = include_str!("../testdata/locals.kt");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_scala() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::Scala).unwrap();
+        let source_code = include_str!("../testdata/locals.scala");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_c_sharp() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::C_Sharp).unwrap();
+        let source_code = include_str!("../testdata/locals.cs");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_c() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::C).unwrap();
+        let source_code = include_str!("../testdata/locals.c");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_cpp() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::Cpp).unwrap();
+        let source_code = include_str!("../testdata/locals.cpp");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_do_zig() -> Result<()> {
+        let mut config = crate::languages::get_local_configuration(BundledParser::Zig).unwrap();
+        let source_code = include_str!("../testdata/locals.zig");
+        let doc = parse_file_for_lang(&mut config, source_code)?;
+
+        let dumped = snapshot_syntax_document(&doc, source_code);
+        insta::assert_snapshot!(dumped);
+
+        Ok(())
+    }
+
+    /// Runs `parse_tree` with a query of its own on some Rust code.
+    fn parse_with_query(query: &str, source_code: &str) -> Result<Vec<Occurrence>> {
+        let language = BundledParser::Rust.get_language();
+        let config = LocalConfiguration {
+            language,
+            query: tree_sitter::Query::new(language, query)?,
+            visibility: Visibility::Ordered,
+        };
+        let mut parser = config.get_parser();
+        let tree = parser.parse(source_code, None).unwrap();
+
+        parse_tree(&config, &tree, source_code.as_bytes())
+    }
+
+    #[test]
+    fn test_invalid_queries_are_errors() {
+        let source_code = "fn main() {\n    let first = 1;\n    first + second;\n}\n";
+
+        for query in [
+            r#"((identifier) @definition.term (#set! "scope" "bogus"))"#,
+            r#"((identifier) @definition.term (#set! "hoist" "yes"))"#,
+            "(let_declaration pattern: (identifier) @definition.term value: (_) @definition.value)",
+            "(binary_expression left: (_) @reference right: (_) @reference)",
+            "(function_item body: (block) @scope) @scope",
+        ] {
+            assert!(
+                parse_with_query(query, source_code).is_err(),
+                "expected an error for {query}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_reference_at_the_start_of_a_scope() -> Result<()> {
+        // The binary expression is a scope of its own here, so that it starts
+        // with the reference to `first`.
+        let query = r#"
+            (block) @scope
+            (binary_expression) @scope
+            (let_declaration pattern: (identifier) @definition.term)
+            (binary_expression right: (identifier) @definition.term)
+            (identifier) @reference
+        "#;
+        let source_code = "fn main() {\n    let first = 1;\n    first + second;\n}\n";
+
+        let occurrences = parse_with_query(query, source_code)?;
+        let symbol_at = |range: &[i32]| {
+            occurrences
+                .iter()
+                .find(|occ| occ.range == range)
+                .map(|occ| occ.symbol.as_str())
+        };
+
+        assert_eq!(symbol_at(&[2, 4, 9]), symbol_at(&[1, 8, 13]));
+        assert!(symbol_at(&[2, 4, 9]).is_some());
+        assert_ne!(symbol_at(&[2, 12, 18]), symbol_at(&[1, 8, 13]));
+
+        Ok(())
+    }
+
+    fn fuzz_sample(language: &str) -> Option<&'static str> {
+        Some(match language {
+            "c" => include_str!("../testdata/locals.c"),
+            "c_sharp" => include_str!("../testdata/locals.cs"),
+            "cpp" => include_str!("../testdata/locals.cpp"),
+            "go" => include_str!("../testdata/locals.go"),
+            "java" => include_str!("../testdata/locals.java"),
+            "javascript" => include_str!("../testdata/locals.js"),
+            "kotlin" => include_str!("../testdata/locals.kt"),
+            "perl" => include_str!("../testdata/perl.pm"),
+            "python" => include_str!("../testdata/locals.py"),
+            "rust" => include_str!("../testdata/locals.rs"),
+            "scala" => include_str!("../testdata/locals.scala"),
+            "tsx" => include_str!("../testdata/locals.tsx"),
+            "typescript" => include_str!("../testdata/locals.ts"),
+            "zig" => include_str!("../testdata/locals.zig"),
+            _ => return None,
+        })
+    }
+
+    /// xorshift, so that the fuzzer is the same on every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound.max(1) as u64) as usize
+        }
+    }
+
+    // Bits of code that change where scopes start and end.
+    const FUZZ_TOKENS: &[&[u8]] = &[
+        b"{", b"}", b"(", b")", b"[", b"]", b"|", b",", b";", b"=>", b"->", b"=", b"x", b" ",
+        b"\n", b"\"",
+    ];
+
+    fn mutate(source: &mut Vec<u8>, rng: &mut Rng) {
+        for _ in 0..=rng.below(4) {
+            let at = rng.below(source.len() + 1);
+            match rng.below(4) {
+                0 => {
+                    let end = (at + rng.below(16)).min(source.len());
+                    source.drain(at..end);
+                }
+                1 => {
+                    let token = FUZZ_TOKENS[rng.below(FUZZ_TOKENS.len())];
+                    source.splice(at..at, token.iter().copied());
+                }
+                2 => {
+                    let end = (at + rng.below(64)).min(source.len());
+                    let copy = source[at..end].to_vec();
+                    let to = rng.below(source.len() + 1);
+                    source.splice(to..to, copy);
+                }
+                _ => source.truncate(at),
+            }
+        }
+    }
+
+    /// Breaks the samples of every language with locals in random ways, and
+    /// checks that `parse_tree` still returns. Set `LOCALS_FUZZ_ITERATIONS`
+    /// to run it for longer.
+    #[test]
+    fn test_parse_tree_never_panics() {
+        let iterations = std::env::var("LOCALS_FUZZ_ITERATIONS")
+            .ok()
+            .and_then(|iterations| iterations.parse().ok())
+            .unwrap_or(200);
+
+        for language in LANGUAGES.iter().filter(|l| l.capabilities.locals) {
+            let config = language
+                .parser
+                .clone()
+                .and_then(crate::languages::get_local_configuration)
+                .unwrap();
+            let sample = fuzz_sample(language.name)
+                .unwrap_or_else(|| panic!("no sample to fuzz {} with", language.name));
+
+            let mut parser = config.get_parser();
+            let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+            for _ in 0..iterations {
+                let mut source = sample.as_bytes().to_vec();
+                mutate(&mut source, &mut rng);
+
+                let tree = parser.parse(&source, None).unwrap();
+                let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    parse_tree(config, &tree, &source)
+                }));
+                assert!(
+                    result.is_ok(),
+                    "parse_tree panicked for {} on:\n{}",
+                    language.name,
+                    String::from_utf8_lossy(&source)
+                );
+            }
+        }
+    }
 }
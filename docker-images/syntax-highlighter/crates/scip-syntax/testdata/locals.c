@@ -0,0 +1,33 @@
+#include <stdio.h>
+
+static int twice(int);
+
+struct point {
+    int x;
+    int y;
+};
+
+static int sum(const int *values, int count) {
+    int total = 0;
+    for (int i = 0; i < count; i++) {
+        total += values[i];
+    }
+    return total;
+}
+
+int main(int argc, char *argv[]) {
+    int values[] = {1, 2, 3};
+    struct point origin = {.x = 0, .y = 0};
+    if (argc > 1) {
+        int total = twice(sum(values, 3));
+        printf("%d %s\n", total, argv[1]);
+    }
+    for (int i = 0; i < argc; i++) {
+        puts(argv[i]);
+    }
+    return origin.x;
+}
+
+static int twice(int n) {
+    return n * 2;
+}
@@ -0,0 +1,29 @@
+#include <map>
+#include <string>
+#include <vector>
+
+int sum(const std::vector<int> &values, int extra = 0) {
+    int total = extra;
+    for (const auto &value : values) {
+        total += value;
+    }
+    return total;
+}
+
+int tally(const std::map<std::string, int> &counts) {
+    int total = 0;
+    for (const auto &[name, count] : counts) {
+        total += count + static_cast<int>(name.size());
+    }
+    return total;
+}
+
+int scale(std::vector<int> values, int factor) {
+    auto [first, last] = std::make_pair(values.front(), values.back());
+    auto times = [factor](int value) { return value * factor; };
+    int total = 0;
+    std::for_each(values.begin(), values.end(), [&total, times](int value) {
+        total += times(value);
+    });
+    return total + first + last + sum(values);
+}
@@ -0,0 +1,42 @@
+class Locals
+{
+    public int Sum(List<int> values, int[] extra)
+    {
+        var total = 0;
+        foreach (var value in values)
+        {
+            total += value;
+        }
+        for (var i = 0; i < extra.Length; i++)
+        {
+            total += extra[i];
+        }
+        return total;
+    }
+
+    public string Read(string path)
+    {
+        try
+        {
+            using (var reader = File.OpenText(path))
+            {
+                return reader.ReadLine();
+            }
+        }
+        catch (IOException error)
+        {
+            return error.Message;
+        }
+    }
+
+    public double Area(object shape)
+    {
+        if (shape is Circle circle)
+        {
+            return circle.Radius * circle.Radius;
+        }
+        Func<double, double> square = side => side * side;
+        Func<double, double, double> multiply = (a, b) => a * b;
+        return square(1.0) + multiply(2.0, 3.0);
+    }
+}
@@ -0,0 +1,29 @@
+class Locals {
+    public int sum(List<Integer> values, int... extra) {
+        int total = 0;
+        for (int value : values) {
+            total += value;
+        }
+        for (int i = 0; i < extra.length; i++) {
+            total += extra[i];
+        }
+        return total;
+    }
+
+    public String read(Path path) {
+        try (var reader = Files.newBufferedReader(path)) {
+            return reader.readLine();
+        } catch (IOException error) {
+            return error.getMessage();
+        }
+    }
+
+    public double area(Object shape) {
+        if (shape instanceof Circle circle) {
+            return circle.radius() * circle.radius();
+        }
+        Function<Double, Double> square = side -> side * side;
+        BiFunction<Double, Double, Double> multiply = (a, b) -> a * b;
+        return square.apply(1.0) + multiply.apply(2.0, 3.0);
+    }
+}
@@ -0,0 +1,37 @@
+const config = { retries: 3, verbose: false }
+
+function request(url, { retries, verbose = config.verbose } = {}, ...handlers) {
+  let attempt = 0
+  for (let i = 0; i < retries; i++) {
+    attempt = i
+  }
+
+  const [primary, [secondary] = []] = handlers
+  const log = (message) => verbose && console.log(message, attempt)
+
+  for (const handler of handlers) {
+    handler(url, primary, secondary).then(result => log(result))
+  }
+
+  try {
+    log(url)
+  } catch ({ message }) {
+    log(message)
+  }
+
+  if (!primary) {
+    var fallback = handlers[handlers.length - 1]
+  }
+  return describe(fallback || primary)
+}
+
+function describe(handler) {
+  return handler.name
+}
+
+class Client {
+  send(url) {
+    const retries = config.retries
+    return request(url, { retries, verbose: true })
+  }
+}
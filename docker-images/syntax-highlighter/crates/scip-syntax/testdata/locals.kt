@@ -0,0 +1,39 @@
+class Locals(private val base: Int) {
+    private val factor = 2
+
+    fun sum(values: List<Int>, vararg extra: Int): Int {
+        var total = base * factor
+        for (value in values) {
+            total += value
+        }
+        for ((index, item) in extra.withIndex()) {
+            total += index * item
+        }
+        return total
+    }
+
+    fun read(path: String): String {
+        try {
+            return File(path).bufferedReader().use { reader -> reader.readLine() }
+        } catch (error: IOException) {
+            return error.message ?: path
+        }
+    }
+
+    fun describe(shape: Any): String {
+        val (width, height) = sizeOf(shape)
+        val label = when (val area = width * height) {
+            0 -> "empty"
+            else -> "area $area"
+        }
+        return listOf(width, height).map { side -> side * factor }.joinToString(separator = label)
+    }
+
+    fun shadow(value: Int): Int {
+        val before = value + offset
+        val value = before * 2
+        return value
+    }
+
+    private val offset = 1
+}
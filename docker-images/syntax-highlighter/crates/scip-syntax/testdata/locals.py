@@ -0,0 +1,27 @@
+import os
+
+
+def outer(path, *args, debug=False, **kwargs):
+    total = 0
+    for index, arg in enumerate(args):
+        total += index * arg
+
+    squares = [value * value for value in range(total)]
+    pairs = {key: item for key, item in kwargs.items()}
+    scale = lambda factor, offset=1: factor * total + offset
+
+    def inner(path):
+        return os.path.join(path, str(debug))
+
+    if (size := len(squares)) > 0:
+        print(size, pairs, scale(2))
+
+    first, (second, third) = args[0], args[1:3]
+    return inner(path), first, second, third
+
+
+class Config:
+    name = "config"
+
+    def describe(self, name=None):
+        return self.name, name or Config.name
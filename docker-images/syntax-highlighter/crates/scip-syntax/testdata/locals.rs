@@ -0,0 +1,51 @@
+fn main() {
+    let x = 1;
+    let x = x + 1;
+    {
+        let x = x * 2;
+        println!("{}", x);
+    }
+
+    let add = |a, b: i32| a + b + x;
+    let (first, mut second) = (add(x, 1), 2);
+    second += first;
+
+    for (i, item) in [first, second].iter().enumerate() {
+        println!("{} {}", i, item);
+    }
+
+    match Some(second) {
+        Some(value) if value > x => println!("{}", value),
+        other => println!("{:?}", other),
+    }
+
+    let Point { x: px, y } = Point { x, y: 2 };
+    println!("{} {}", px, helper(y));
+}
+
+fn helper(x: i32) -> i32 {
+    fn max(x: i32) -> i32 {
+        std::cmp::max(x, 0)
+    }
+    max(x)
+}
+
+fn shadowed() -> i32 {
+    let total = 1;
+    let doubled = total * 2;
+    let total = doubled + total;
+    total
+}
+
+fn destructured(x: Option<i32>, a: i32, b: i32) -> i32 {
+    let (a, b) = (b, a);
+    let mut x = x;
+    if let Some(x) = x {
+        return x + a;
+    }
+    while let Some(value) = x {
+        x = value.checked_sub(b);
+    }
+    let Some(y) = x else { return b };
+    y
+}
@@ -0,0 +1,29 @@
+object Locals {
+  val factor = 2
+
+  def sum(values: List[Int], extra: Int*): Int = {
+    var total = 0
+    for (value <- values; doubled = value * factor) {
+      total += doubled
+    }
+    total + extra.sum
+  }
+
+  def read(path: String): String =
+    try {
+      Using.resource(Source.fromFile(path)) { source => source.mkString }
+    } catch {
+      case error: IOException => error.getMessage
+    }
+
+  def describe(shape: Shape): String = shape match {
+    case Circle(radius) => s"circle $radius"
+    case rect @ Rect(width, height) if width == height => s"square $rect"
+    case other => other.toString
+  }
+
+  def pairs(values: List[(String, Int)]): List[Int] = {
+    val (names, counts) = values.unzip
+    values.map((name, count) => name.length + count) ++ counts
+  }
+}
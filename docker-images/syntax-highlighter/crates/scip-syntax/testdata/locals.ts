@@ -0,0 +1,33 @@
+export function summarize(items: number[], scale = 2, ...labels: string[]): string {
+    let total = 0
+    for (const item of items) {
+        total += item * scale
+    }
+
+    const { length, extra: renamed = 0 } = labels
+    const [first, ...others] = items
+    const double = (value: number, offset?: number) => value * scale + (offset ?? total)
+
+    try {
+        total = double(first) + renamed
+    } catch (error) {
+        console.log(error, others)
+    }
+
+    {
+        let total = length
+        console.log(total)
+    }
+
+    if (others.length > 0) {
+        var rest = format(others)
+    }
+
+    return labels.map(function (label, index) {
+        return `${label}: ${index * total}`
+    }).join(', ') + (rest ?? '')
+}
+
+function format(values: number[]): string {
+    return values.join(' ')
+}
@@ -0,0 +1,12 @@
+export function List({ items, render }: ListProps) {
+    const [selected, setSelected] = useState(0)
+    return (
+        <ul>
+            {items.map((item, index) => (
+                <li key={index} onClick={() => setSelected(index)}>
+                    {render(item, index === selected)}
+                </li>
+            ))}
+        </ul>
+    )
+}
@@ -0,0 +1,31 @@
+const std = @import("std");
+
+fn sum(values: []const i32, extra: i32) i32 {
+    var total: i32 = extra;
+    for (values) |value, i| {
+        total += value * @intCast(i32, i);
+    }
+    return total;
+}
+
+fn first(values: []const i32) ?i32 {
+    if (values.len > 0) {
+        return values[0];
+    }
+    return null;
+}
+
+fn orZero(values: []const i32) i32 {
+    if (first(values)) |value| {
+        return value;
+    }
+    var rest = values;
+    while (rest.len > 0) : (rest = rest[1..]) {
+        std.debug.print("{}\n", .{rest[0]});
+    }
+    const parsed = std.fmt.parseInt(i32, "1", 10) catch |err| {
+        std.debug.print("{}\n", .{err});
+        return 0;
+    };
+    return parsed;
+}
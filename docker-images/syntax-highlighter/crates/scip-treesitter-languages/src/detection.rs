@@ -0,0 +1,270 @@
+//! Guessing the language of a file when the caller doesn't say which it is.
+//!
+//! Like linguist, this tries the strategies that are least likely to be wrong
+//! first: a modeline, the name of the file, a shebang, the extension, and
+//! finally, for files without an extension, what the code looks like.
+
+use std::path::Path;
+
+use crate::{parsers::BundledParser, registry};
+
+/// How sure [`detect_language`] is about its answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// Only the contents look like the language. Files with an extension
+    /// we don't know, like `.txt` or `.md`, are never detected this way.
+    Low,
+    /// The extension belongs to the language, though some extensions are
+    /// shared with languages we don't have a parser for.
+    Medium,
+    /// The file says what it is, with a modeline or a shebang, or has a
+    /// well-known name.
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    pub parser: BundledParser,
+    pub confidence: Confidence,
+}
+
+// Modelines are at the start or the end of a file. Looking at more than this
+// many lines for them or for heuristics doesn't change the answer much, but
+// takes longer for large files.
+const MODELINE_LINES: usize = 5;
+const HEURISTIC_LINES: usize = 200;
+const HEAD_BYTES: usize = 16 * 1024;
+
+pub fn detect_language(filename: &str, content: &[u8]) -> Option<Detection> {
+    let head = String::from_utf8_lossy(&content[..content.len().min(HEAD_BYTES)]);
+    let tail = String::from_utf8_lossy(&content[content.len().saturating_sub(HEAD_BYTES)..]);
+    let path = Path::new(filename);
+
+    let detected = |parser, confidence| Some(Detection { parser, confidence });
+    if let Some(parser) = head
+        .lines()
+        .take(MODELINE_LINES)
+        .chain(tail.lines().rev().take(MODELINE_LINES))
+        .find_map(modeline)
+    {
+        return detected(parser, Confidence::High);
+    }
+    if let Some(parser) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(from_filename)
+    {
+        return detected(parser, Confidence::High);
+    }
+    if let Some(parser) = head.lines().next().and_then(shebang) {
+        return detected(parser, Confidence::High);
+    }
+    if let Some(extension) = path.extension() {
+        // An extension that isn't in the registry most likely belongs to
+        // prose or data, which code snippets in it don't turn into code.
+        let parser = extension
+            .to_str()
+            .and_then(|extension| registry::by_extension(extension, content)?.parser.clone())?;
+        return detected(parser, Confidence::Medium);
+    }
+    if let Some(parser) = heuristics(&head) {
+        return detected(parser, Confidence::Low);
+    }
+    None
+}
+
+/// Editors name languages in modelines, see the aliases in the registry.
+fn from_editor_name(name: &str) -> Option<BundledParser> {
+    registry::by_name(name)?.parser.clone()
+}
+
+/// Reads vim (`vim: set ft=python:`) and emacs (`-*- mode: python -*-`)
+/// modelines.
+fn modeline(line: &str) -> Option<BundledParser> {
+    if let Some(start) = line.find("-*-") {
+        let rest = &line[start + 3..];
+        let variables = &rest[..rest.find("-*-")?];
+        if !variables.contains(':') {
+            return from_editor_name(variables.trim());
+        }
+        return variables.split(';').find_map(|variable| {
+            let (name, value) = variable.split_once(':')?;
+            match name.trim() {
+                "mode" => from_editor_name(value.trim()),
+                _ => None,
+            }
+        });
+    }
+
+    // The marker has to be a word of its own, so that `index: 1` isn't one.
+    let start = ["vim:", "vi:", "ex:"]
+        .iter()
+        .filter_map(|prefix| {
+            let start = line.find(prefix)?;
+            let before = &line[..start];
+            (before.is_empty() || before.ends_with(char::is_whitespace))
+                .then_some(start + prefix.len())
+        })
+        .min()?;
+    line[start..]
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            let (name, value) = option.split_once('=')?;
+            match name {
+                "ft" | "filetype" | "syntax" | "syn" => from_editor_name(value),
+                _ => None,
+            }
+        })
+}
+
+fn from_filename(name: &str) -> Option<BundledParser> {
+    registry::by_filename(name)?.parser.clone()
+}
+
+/// Reads the interpreter from `#!/usr/bin/python3` or `#!/usr/bin/env -S node`.
+fn shebang(line: &str) -> Option<BundledParser> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+
+    // python3.11 is still python.
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    registry::by_interpreter(interpreter)?.parser.clone()
+}
+
+/// Lines that are typical for a language. Each matching line is a vote for
+/// its language, so a hint that other languages share is fine as long as it
+/// isn't the only one that matches.
+#[rustfmt::skip]
+const HINTS: &[(BundledParser, fn(&str) -> bool)] = &[
+    (BundledParser::C,          |l| l.starts_with("#include <") && l.ends_with(".h>")),
+    (BundledParser::C,          |l| l.starts_with("typedef struct")),
+    (BundledParser::Cpp,        |l| l.starts_with("#include <") && !l.ends_with(".h>")),
+    (BundledParser::Cpp,        |l| l.contains("std::")),
+    (BundledParser::Cpp,        |l| l.starts_with("template <") || l.starts_with("template<")),
+    (BundledParser::C_Sharp,    |l| l.starts_with("using System")),
+    (BundledParser::C_Sharp,    |l| l.starts_with("namespace ") && l.ends_with(';')),
+    (BundledParser::Go,         |l| l.starts_with("package ") && !l.ends_with(';') && !l.contains('.')),
+    (BundledParser::Go,         |l| l.starts_with("func ")),
+    (BundledParser::Go,         |l| l.contains(" := ")),
+    (BundledParser::Java,       |l| l.starts_with("package ") && l.ends_with(';')),
+    (BundledParser::Java,       |l| l.starts_with("import java.")),
+    (BundledParser::Java,       |l| l.starts_with("public class ") || l.starts_with("public static void ")),
+    (BundledParser::Javascript, |l| l.contains("require(") && l.starts_with("const ")),
+    (BundledParser::Javascript, |l| l.starts_with("module.exports")),
+    (BundledParser::Kotlin,     |l| l.starts_with("fun ")),
+    (BundledParser::Kotlin,     |l| l.starts_with("data class ")),
+    (BundledParser::Perl,       |l| l.starts_with("use strict;") || l.starts_with("use warnings;")),
+    (BundledParser::Perl,       |l| l.starts_with("my $") || l.starts_with("my @")),
+    (BundledParser::Python,     |l| l.starts_with("def ") && l.ends_with(':')),
+    (BundledParser::Python,     |l| l.starts_with("from ") && l.contains(" import ")),
+    (BundledParser::Python,     |l| l.starts_with("if __name__ ==")),
+    (BundledParser::Ruby,       |l| l.starts_with("require '") || l.starts_with("require \"")),
+    (BundledParser::Ruby,       |l| l.starts_with("def ") && !l.ends_with(':') && !l.contains('=')),
+    (BundledParser::Ruby,       |l| l.starts_with("module ") && !l.ends_with(';')),
+    (BundledParser::Rust,       |l| l.starts_with("fn ") || l.starts_with("pub fn ")),
+    (BundledParser::Rust,       |l| l.starts_with("use ") && l.contains("::")),
+    (BundledParser::Rust,       |l| l.starts_with("#[") || l.starts_with("impl ")),
+    (BundledParser::Scala,      |l| l.starts_with("object ") || l.starts_with("case class ")),
+    (BundledParser::Scala,      |l| l.starts_with("def ") && l.contains('=')),
+    (BundledParser::Sql,        |l| ["SELECT ", "CREATE TABLE ", "INSERT INTO "].iter().any(|s| l.starts_with(s))),
+    (BundledParser::Typescript, |l| l.starts_with("interface ") || l.starts_with("export interface ")),
+    (BundledParser::Typescript, |l| l.starts_with("import type ") || l.starts_with("export type ")),
+    (BundledParser::Zig,        |l| l.contains("@import(\"std\")")),
+];
+
+/// Picks the language with the most hints, unless there's a tie.
+fn heuristics(head: &str) -> Option<BundledParser> {
+    let mut votes = Vec::<(BundledParser, usize)>::new();
+    for line in head.lines().take(HEURISTIC_LINES) {
+        let line = line.trim();
+        for (parser, hint) in HINTS {
+            if !hint(line) {
+                continue;
+            }
+            match votes.iter_mut().find(|(voted, _)| voted == parser) {
+                Some((_, count)) => *count += 1,
+                None => votes.push((parser.clone(), 1)),
+            }
+        }
+    }
+
+    votes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    match votes.as_slice() {
+        [(parser, _)] => Some(parser.clone()),
+        [(parser, first), (_, second), ..] if first > second => Some(parser.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn detect(filename: &str, content: &str) -> Option<(BundledParser, Confidence)> {
+        detect_language(filename, content.as_bytes())
+            .map(|detection| (detection.parser, detection.confidence))
+    }
+
+    #[test]
+    fn detects_by_strategy() {
+        assert_eq!(
+            detect("main.go", "package main\n"),
+            Some((BundledParser::Go, Confidence::Medium))
+        );
+        assert_eq!(
+            detect("Rakefile", "task :default\n"),
+            Some((BundledParser::Ruby, Confidence::High))
+        );
+        assert_eq!(
+            detect("run", "#!/usr/bin/env python3\nprint(1)\n"),
+            Some((BundledParser::Python, Confidence::High))
+        );
+        assert_eq!(
+            detect("run", "#!/usr/local/bin/node\n"),
+            Some((BundledParser::Javascript, Confidence::High))
+        );
+        assert_eq!(
+            detect("script", "x = 1\n# vim: set ft=ruby ts=2:\n"),
+            Some((BundledParser::Ruby, Confidence::High))
+        );
+        assert_eq!(
+            detect(
+                "script.js",
+                "// -*- mode: typescript; indent-tabs-mode: nil -*-\n"
+            ),
+            Some((BundledParser::Typescript, Confidence::High))
+        );
+        assert_eq!(detect("README", "Nothing to see here.\n"), None);
+    }
+
+    #[test]
+    fn detects_by_content() {
+        let go = "package main\n\nimport \"fmt\"\n\nfunc main() {\n\tx := 1\n\tfmt.Println(x)\n}\n";
+        assert_eq!(
+            detect("main", go),
+            Some((BundledParser::Go, Confidence::Low))
+        );
+
+        let python = "from os import path\n\ndef main():\n    print(path.sep)\n";
+        assert_eq!(
+            detect("main", python),
+            Some((BundledParser::Python, Confidence::Low))
+        );
+
+        let cpp = "#include <vector>\n\nint main() {\n  std::vector<int> v;\n}\n";
+        assert_eq!(
+            detect("main", cpp),
+            Some((BundledParser::Cpp, Confidence::Low))
+        );
+
+        // Looks like Python and like Ruby.
+        assert_eq!(detect("main", "def a:\ndef b\n"), None);
+
+        // Only files without an extension are guessed from their contents.
+        assert_eq!(detect("notes.txt", python), None);
+        assert_eq!(detect("README.md", cpp), None);
+    }
+}
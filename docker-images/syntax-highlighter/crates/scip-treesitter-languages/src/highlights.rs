@@ -1,6 +1,4 @@
-use std::collections::HashMap;
-
-use paste::paste;
+use once_cell::sync::OnceCell;
 use scip::types::SyntaxKind;
 use scip_macros::include_scip_query;
 use tree_sitter_highlight::{Highlight, HighlightConfiguration};
@@ -72,101 +70,108 @@ const MATCHES_TO_SYNTAX_KINDS: &[(&str, SyntaxKind)] = &[
     ("variable.module",         SyntaxKind::IdentifierModule),
 ];
 
-/// Add a language highlight configuration to the CONFIGURATIONS global.
+/// Defines `get_parser_configuration`, which builds the highlight configuration
+/// for a parser the first time it is needed.
+///
+/// Compiling the queries is expensive, so this makes cold start and memory use
+/// scale with the languages that actually get highlighted. Each parser gets its
+/// own cell, so loading one language never blocks highlighting another.
 ///
 /// This makes it so you don't have to understand how configurations are added,
 /// just add the name of filetype that you want.
 macro_rules! create_configurations {
-    ( $($name: tt),* ) => {{
-        use crate::parsers::BundledParser;
-
-        let mut m = HashMap::new();
-        let highlight_names = MATCHES_TO_SYNTAX_KINDS.iter().map(|hl| hl.0).collect::<Vec<&str>>();
-
-        $(
-            {
-                // Create HighlightConfiguration language
-                let mut lang = HighlightConfiguration::new(
-                    paste! { BundledParser::$name.get_language() },
-                    include_scip_query!($name, "highlights"),
-                    include_scip_query!($name, "injections"),
-                    include_scip_query!($name, "locals"),
-                ).expect(stringify!("parser for '{}' must be compiled", $name));
-
-                // Associate highlights with configuration
-                lang.configure(&highlight_names);
+    ( $($name: tt),* ) => {
+        /// Returns the highlight configuration for `parser`, building it if
+        /// this is the first time it is used.
+        pub fn get_parser_configuration(parser: &BundledParser) -> &'static HighlightConfiguration {
+            match parser {
+                $(
+                    BundledParser::$name => {
+                        static CONFIGURATION: OnceCell<HighlightConfiguration> = OnceCell::new();
+                        CONFIGURATION.get_or_init(|| {
+                            new_configuration(
+                                parser,
+                                include_scip_query!($name, "highlights"),
+                                include_scip_query!($name, "injections"),
+                                include_scip_query!($name, "locals"),
+                            )
+                        })
+                    }
+                )*
 
-                // Insert into configurations, so we only create once at startup.
-                m.insert(BundledParser::$name, lang);
+                // The typescript and tsx highlights build on top of the
+                // javascript ones, so they are combined by hand.
+                BundledParser::Typescript => {
+                    static CONFIGURATION: OnceCell<HighlightConfiguration> = OnceCell::new();
+                    CONFIGURATION.get_or_init(|| {
+                        let highlights = vec![
+                            include_scip_query!("typescript", "highlights"),
+                            include_scip_query!("javascript", "highlights"),
+                        ];
+                        new_configuration(
+                            parser,
+                            &highlights.join("\n"),
+                            include_scip_query!("typescript", "injections"),
+                            include_scip_query!("typescript", "locals"),
+                        )
+                    })
+                }
+                BundledParser::Tsx => {
+                    static CONFIGURATION: OnceCell<HighlightConfiguration> = OnceCell::new();
+                    CONFIGURATION.get_or_init(|| {
+                        let highlights = vec![
+                            include_scip_query!("tsx", "highlights"),
+                            include_scip_query!("typescript", "highlights"),
+                            include_scip_query!("javascript", "highlights"),
+                        ];
+                        new_configuration(
+                            parser,
+                            &highlights.join("\n"),
+                            include_scip_query!("tsx", "injections"),
+                            include_scip_query!("tsx", "locals"),
+                        )
+                    })
+                }
             }
-        )*
-
-        // Manually insert the typescript and tsx languages because the
-        // tree-sitter-typescript crate doesn't have a language() function.
-        {
-            let highlights = vec![
-                include_scip_query!("typescript", "highlights"),
-                include_scip_query!("javascript", "highlights"),
-            ];
-            let mut lang = HighlightConfiguration::new(
-                BundledParser::Typescript.get_language(),
-                &highlights.join("\n"),
-                include_scip_query!("typescript", "injections"),
-                include_scip_query!("typescript", "locals"),
-            ).expect("parser for 'typescript' must be compiled");
-            lang.configure(&highlight_names);
-            m.insert(BundledParser::Typescript, lang);
-        }
-        {
-            let highlights = vec![
-                include_scip_query!("tsx", "highlights"),
-                include_scip_query!("typescript", "highlights"),
-                include_scip_query!("javascript", "highlights"),
-            ];
-            let mut lang = HighlightConfiguration::new(
-                BundledParser::Tsx.get_language(),
-                &highlights.join("\n"),
-                include_scip_query!("tsx", "injections"),
-                include_scip_query!("tsx", "locals"),
-            ).expect("parser for 'tsx' must be compiled");
-            lang.configure(&highlight_names);
-            m.insert(BundledParser::Tsx, lang);
         }
-
-        m
-    }}
+    }
 }
 
-lazy_static::lazy_static! {
-    pub static ref CONFIGURATIONS: HashMap<BundledParser, HighlightConfiguration> = {
-        // NOTE: typescript/tsx crates are included, even though not listed below.
+// You can add any new crate::parsers::Parser variants here.
+// NOTE: typescript/tsx are handled by the macro, even though not listed below.
+create_configurations!(
+    C, Cpp, C_Sharp, Go, Java, Javascript, Jsonnet, Kotlin, Nickel, Perl, Pod, Python, Ruby, Rust,
+    Scala, Sql, Xlsg, Zig
+);
+
+fn new_configuration(
+    parser: &BundledParser,
+    highlights: &str,
+    injections: &str,
+    locals: &str,
+) -> HighlightConfiguration {
+    let mut configuration =
+        HighlightConfiguration::new(parser.get_language(), highlights, injections, locals)
+            .unwrap_or_else(|err| {
+                panic!(
+                    "parser for '{}' must be compiled: {:?}",
+                    parser.get_language_name(),
+                    err
+                )
+            });
+
+    // Associate highlights with configuration
+    let highlight_names = MATCHES_TO_SYNTAX_KINDS
+        .iter()
+        .map(|hl| hl.0)
+        .collect::<Vec<&str>>();
+    configuration.configure(&highlight_names);
 
-        // You can add any new crate::parsers::Parser variants here.
-        create_configurations!(
-            C,
-            Cpp,
-            C_Sharp,
-            Go,
-            Java,
-            Javascript,
-            Jsonnet,
-            Kotlin,
-            Nickel,
-            Perl,
-            Pod,
-            Python,
-            Ruby,
-            Rust,
-            Scala,
-            Sql,
-            Xlsg,
-            Zig
-        )
-    };
+    configuration
 }
 
 pub fn get_highlighting_configuration(filetype: &str) -> Option<&'static HighlightConfiguration> {
-    BundledParser::get_parser(filetype).and_then(|parser| CONFIGURATIONS.get(&parser))
+    BundledParser::get_parser(filetype).map(|parser| get_parser_configuration(&parser))
 }
 
 pub fn get_syntax_kind_for_hl(hl: Highlight) -> SyntaxKind {
@@ -1,2 +1,4 @@
+pub mod detection;
 pub mod highlights;
 pub mod parsers;
+pub mod registry;
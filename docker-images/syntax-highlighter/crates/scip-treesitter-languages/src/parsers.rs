@@ -1,5 +1,7 @@
 use tree_sitter::Language;
 
+use crate::registry;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BundledParser {
     C,
@@ -54,80 +56,18 @@ impl BundledParser {
     }
 
     pub fn get_parser(name: &str) -> Option<Self> {
-        match name {
-            "c" => Some(BundledParser::C),
-            "cpp" => Some(BundledParser::Cpp),
-            "c_sharp" => Some(BundledParser::C_Sharp),
-            "go" => Some(BundledParser::Go),
-            "java" => Some(BundledParser::Java),
-            "javascript" => Some(BundledParser::Javascript),
-            "jsonnet" => Some(BundledParser::Jsonnet),
-            "kotlin" => Some(BundledParser::Kotlin),
-            "nickel" => Some(BundledParser::Nickel),
-            "perl" => Some(BundledParser::Perl),
-            "pod" => Some(BundledParser::Pod),
-            "python" => Some(BundledParser::Python),
-            "ruby" => Some(BundledParser::Ruby),
-            "rust" => Some(BundledParser::Rust),
-            "scala" => Some(BundledParser::Scala),
-            "sql" => Some(BundledParser::Sql),
-            "typescript" => Some(BundledParser::Typescript),
-            "tsx" => Some(BundledParser::Tsx),
-            "xlsg" => Some(BundledParser::Xlsg),
-            "zig" => Some(BundledParser::Zig),
-            _ => None,
-        }
+        registry::by_name(name)?.parser.clone()
     }
 
     pub fn get_language_name(&self) -> &str {
-        match self {
-            BundledParser::C => "c",
-            BundledParser::Cpp => "cpp",
-            BundledParser::C_Sharp => "c_sharp",
-            BundledParser::Go => "go",
-            BundledParser::Java => "java",
-            BundledParser::Javascript => "javascript",
-            BundledParser::Jsonnet => "jsonnet",
-            BundledParser::Kotlin => "kotlin",
-            BundledParser::Nickel => "nickel",
-            BundledParser::Perl => "perl",
-            BundledParser::Pod => "pod",
-            BundledParser::Python => "python",
-            BundledParser::Ruby => "ruby",
-            BundledParser::Rust => "rust",
-            BundledParser::Scala => "scala",
-            BundledParser::Sql => "sql",
-            BundledParser::Typescript => "typescript",
-            BundledParser::Tsx => "tsx",
-            BundledParser::Xlsg => "xlsg",
-            BundledParser::Zig => "zig",
-        }
+        registry::by_parser(self).name
     }
 
-    // TODO(SuperAuguste): language detection library
+    /// Goes by the extension alone, so an extension that several languages
+    /// share, like `.h`, always gets the same one (C). Use
+    /// [`detect_language`](crate::detection::detect_language) when the
+    /// content of the file is known.
     pub fn get_parser_from_extension(name: &str) -> Option<Self> {
-        match name {
-            "c" => Some(BundledParser::C),
-            "cpp" => Some(BundledParser::Cpp),
-            "cs" => Some(BundledParser::C_Sharp),
-            "go" => Some(BundledParser::Go),
-            "java" => Some(BundledParser::Java),
-            "js" => Some(BundledParser::Javascript),
-            "jsonnet" => Some(BundledParser::Jsonnet),
-            "kt" => Some(BundledParser::Kotlin),
-            "ncl" => Some(BundledParser::Nickel),
-            "pl" => Some(BundledParser::Perl),
-            "pod" => Some(BundledParser::Pod),
-            "py" => Some(BundledParser::Python),
-            "rb" => Some(BundledParser::Ruby),
-            "rs" => Some(BundledParser::Rust),
-            "scala" => Some(BundledParser::Scala),
-            "sql" => Some(BundledParser::Sql),
-            "ts" => Some(BundledParser::Typescript),
-            "tsx" => Some(BundledParser::Tsx),
-            "xlsg" => Some(BundledParser::Xlsg),
-            "zig" => Some(BundledParser::Zig),
-            _ => None,
-        }
+        registry::by_extension(name, &[])?.parser.clone()
     }
 }
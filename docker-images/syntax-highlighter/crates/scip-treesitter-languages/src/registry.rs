@@ -0,0 +1,439 @@
+//! Every language we know something about, and how to find it.
+//!
+//! Lookups by name, extension, file name, interpreter or syntect syntax all
+//! go through [`LANGUAGES`], so adding a language or an extension only takes
+//! a new entry or field here. Languages that only syntect knows about aren't
+//! listed, they keep their lowercased syntect name.
+
+use crate::parsers::BundledParser;
+
+/// What the tree-sitter engines can do with a language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Syntax highlighting.
+    pub highlights: bool,
+    /// Global symbols, for `/symbols` and ctags.
+    pub tags: bool,
+    /// Local definitions and references.
+    pub locals: bool,
+}
+
+#[derive(Debug)]
+pub struct Language {
+    /// The name used as `filetype` and in metrics, e.g. `c_sharp`.
+    pub name: &'static str,
+    /// Other names for the language, e.g. the ones editors use in modelines.
+    pub aliases: &'static [&'static str],
+    pub extensions: &'static [&'static str],
+    /// Whole file names, for files that don't have an extension.
+    pub filenames: &'static [&'static str],
+    /// Interpreters that show up in shebangs, without a version.
+    pub interpreters: &'static [&'static str],
+    /// Extensions that belong to another language, unless `claims` returns
+    /// true for the contents of the file, e.g. `.h` for C++.
+    pub shared_extensions: &'static [&'static str],
+    pub claims: Option<fn(&[u8]) -> bool>,
+    /// Names of the syntect syntaxes for the language, in order of preference.
+    pub syntect: &'static [&'static str],
+    /// Pick the syntect syntax for the extensions from here, because syntect
+    /// can't tell the languages that share them apart.
+    pub override_syntect: bool,
+    pub parser: Option<BundledParser>,
+    pub capabilities: Capabilities,
+}
+
+const NONE: Capabilities = Capabilities {
+    highlights: false,
+    tags: false,
+    locals: false,
+};
+
+const DEFAULT: Language = Language {
+    name: "",
+    aliases: &[],
+    extensions: &[],
+    filenames: &[],
+    interpreters: &[],
+    shared_extensions: &[],
+    claims: None,
+    syntect: &[],
+    override_syntect: false,
+    parser: None,
+    capabilities: NONE,
+};
+
+const HIGHLIGHTS: Capabilities = Capabilities {
+    highlights: true,
+    ..NONE
+};
+
+const TAGS: Capabilities = Capabilities {
+    tags: true,
+    ..HIGHLIGHTS
+};
+
+const LOCALS: Capabilities = Capabilities {
+    locals: true,
+    ..TAGS
+};
+
+pub static LANGUAGES: &[Language] = &[
+    Language {
+        name: "apex",
+        extensions: &["cls"],
+        syntect: &["Apex"],
+        override_syntect: true,
+        ..DEFAULT
+    },
+    Language {
+        name: "c",
+        extensions: &["c", "h"],
+        syntect: &["C"],
+        override_syntect: true,
+        parser: Some(BundledParser::C),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "cpp",
+        aliases: &["c++"],
+        extensions: &[
+            "cpp", "cc", "cxx", "c++", "cp", "hpp", "hh", "hxx", "h++", "ipp", "tpp", "inl",
+        ],
+        shared_extensions: &["h"],
+        claims: Some(looks_like_cpp),
+        syntect: &["C++"],
+        override_syntect: true,
+        parser: Some(BundledParser::Cpp),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "c_sharp",
+        aliases: &["c#", "csharp", "cs"],
+        extensions: &["cs", "csx"],
+        syntect: &["C#"],
+        parser: Some(BundledParser::C_Sharp),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "go",
+        aliases: &["golang"],
+        extensions: &["go"],
+        syntect: &["Go"],
+        parser: Some(BundledParser::Go),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "java",
+        extensions: &["java"],
+        syntect: &["Java"],
+        parser: Some(BundledParser::Java),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "javascript",
+        aliases: &["js", "js2", "node", "rjsx"],
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        filenames: &["Jakefile"],
+        interpreters: &["node", "nodejs"],
+        syntect: &["JavaScript", "JS Custom - React"],
+        parser: Some(BundledParser::Javascript),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "jsonnet",
+        extensions: &["jsonnet", "libsonnet"],
+        interpreters: &["jsonnet"],
+        parser: Some(BundledParser::Jsonnet),
+        capabilities: HIGHLIGHTS,
+        ..DEFAULT
+    },
+    Language {
+        name: "kotlin",
+        aliases: &["kt"],
+        extensions: &["kt", "kts"],
+        interpreters: &["kotlin", "kotlinc"],
+        syntect: &["Kotlin"],
+        parser: Some(BundledParser::Kotlin),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "nickel",
+        extensions: &["ncl"],
+        parser: Some(BundledParser::Nickel),
+        capabilities: HIGHLIGHTS,
+        ..DEFAULT
+    },
+    Language {
+        name: "perl",
+        aliases: &["cperl"],
+        extensions: &["pl", "pm", "t", "plx", "psgi"],
+        filenames: &["Makefile.PL", "Build.PL", "cpanfile"],
+        interpreters: &["perl"],
+        syntect: &["Perl"],
+        parser: Some(BundledParser::Perl),
+        capabilities: Capabilities {
+            locals: true,
+            ..HIGHLIGHTS
+        },
+        ..DEFAULT
+    },
+    Language {
+        name: "pod",
+        extensions: &["pod"],
+        parser: Some(BundledParser::Pod),
+        capabilities: HIGHLIGHTS,
+        ..DEFAULT
+    },
+    Language {
+        name: "python",
+        aliases: &["python3"],
+        extensions: &["py", "pyi", "pyw"],
+        filenames: &["SConscript", "SConstruct", "wscript", ".pythonrc"],
+        interpreters: &["python", "pypy"],
+        syntect: &["Python"],
+        parser: Some(BundledParser::Python),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "ruby",
+        extensions: &[
+            "rb", "rake", "gemspec", "ru", "rbi", "builder", "jbuilder", "podspec", "thor",
+        ],
+        filenames: &[
+            "Appfile",
+            "Berksfile",
+            "Brewfile",
+            "Capfile",
+            "Dangerfile",
+            "Fastfile",
+            "Gemfile",
+            "Guardfile",
+            "Podfile",
+            "Rakefile",
+            "Thorfile",
+            "Vagrantfile",
+            ".irbrc",
+            ".pryrc",
+        ],
+        interpreters: &["ruby", "jruby"],
+        syntect: &["Ruby"],
+        parser: Some(BundledParser::Ruby),
+        capabilities: TAGS,
+        ..DEFAULT
+    },
+    Language {
+        name: "rust",
+        aliases: &["rustic"],
+        extensions: &["rs"],
+        syntect: &["Rust Enhanced"],
+        parser: Some(BundledParser::Rust),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "scala",
+        extensions: &["scala", "sc", "sbt"],
+        interpreters: &["scala"],
+        syntect: &["Scala"],
+        parser: Some(BundledParser::Scala),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "sql",
+        extensions: &["sql"],
+        syntect: &["SQL"],
+        parser: Some(BundledParser::Sql),
+        capabilities: HIGHLIGHTS,
+        ..DEFAULT
+    },
+    Language {
+        name: "tex",
+        shared_extensions: &["cls"],
+        claims: Some(|content| content.starts_with(b"%") || content.starts_with(b"\\")),
+        syntect: &["TeX"],
+        override_syntect: true,
+        ..DEFAULT
+    },
+    Language {
+        name: "typescript",
+        aliases: &["ts"],
+        extensions: &["ts", "mts", "cts"],
+        interpreters: &["deno", "ts-node"],
+        syntect: &["TypeScript", "TypeScriptReact"],
+        parser: Some(BundledParser::Typescript),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+    Language {
+        name: "tsx",
+        aliases: &["typescriptreact", "typescript-tsx"],
+        extensions: &["tsx"],
+        syntect: &["TypeScriptReact"],
+        parser: Some(BundledParser::Tsx),
+        capabilities: Capabilities {
+            locals: true,
+            ..HIGHLIGHTS
+        },
+        ..DEFAULT
+    },
+    Language {
+        name: "xlsg",
+        extensions: &["xlsg"],
+        syntect: &["xlsg"],
+        override_syntect: true,
+        parser: Some(BundledParser::Xlsg),
+        capabilities: HIGHLIGHTS,
+        ..DEFAULT
+    },
+    Language {
+        name: "zig",
+        extensions: &["zig"],
+        parser: Some(BundledParser::Zig),
+        capabilities: LOCALS,
+        ..DEFAULT
+    },
+];
+
+/// Finds a language by its name or one of its aliases, ignoring case.
+pub fn by_name(name: &str) -> Option<&'static Language> {
+    LANGUAGES.iter().find(|language| {
+        language.name.eq_ignore_ascii_case(name)
+            || language
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Finds the language of files with `extension`. A language that shares the
+/// extension wins if it claims `content`.
+pub fn by_extension(extension: &str, content: &[u8]) -> Option<&'static Language> {
+    LANGUAGES
+        .iter()
+        .find(|language| {
+            language.shared_extensions.contains(&extension)
+                && language.claims.map_or(false, |claims| claims(content))
+        })
+        .or_else(|| {
+            LANGUAGES
+                .iter()
+                .find(|language| language.extensions.contains(&extension))
+        })
+}
+
+// Headers don't say which language they are for, so this looks for things
+// that C doesn't have. Only the start of the file is checked, which is where
+// a C++ header usually includes the standard library or opens a namespace.
+fn looks_like_cpp(content: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&content[..content.len().min(8 * 1024)]);
+    head.lines().map(str::trim_start).any(|line| {
+        [
+            "class ",
+            "namespace ",
+            "template",
+            "using ",
+            "public:",
+            "private:",
+            "protected:",
+        ]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+            || (line.starts_with("#include <") && !line.contains(".h>"))
+            || line.contains("std::")
+    })
+}
+
+pub fn by_filename(filename: &str) -> Option<&'static Language> {
+    LANGUAGES
+        .iter()
+        .find(|language| language.filenames.contains(&filename))
+}
+
+pub fn by_interpreter(interpreter: &str) -> Option<&'static Language> {
+    LANGUAGES
+        .iter()
+        .find(|language| language.interpreters.contains(&interpreter))
+}
+
+/// Finds the language of a syntect syntax. Some syntaxes are used for more
+/// than one language, so this prefers the one `extension` belongs to.
+pub fn by_syntect_name(name: &str, extension: &str) -> Option<&'static Language> {
+    let mut candidates = LANGUAGES
+        .iter()
+        .filter(|language| language.syntect.contains(&name));
+    let first = candidates.clone().next();
+    candidates
+        .find(|language| language.extensions.contains(&extension))
+        .or(first)
+}
+
+pub fn by_parser(parser: &BundledParser) -> &'static Language {
+    LANGUAGES
+        .iter()
+        .find(|language| language.parser.as_ref() == Some(parser))
+        .expect("every parser to be in the registry")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parsers_round_trip() {
+        for language in LANGUAGES {
+            if let Some(parser) = &language.parser {
+                assert_eq!(by_parser(parser).name, language.name);
+                assert_eq!(
+                    BundledParser::get_parser(language.name).as_ref(),
+                    Some(parser)
+                );
+            }
+            assert_eq!(language.capabilities.highlights, language.parser.is_some());
+            assert_eq!(by_name(language.name).unwrap().name, language.name);
+        }
+    }
+
+    #[test]
+    fn shared_names() {
+        assert_eq!(by_name("C++").unwrap().name, "cpp");
+        assert_eq!(
+            by_extension("cls", b"public class A {}").unwrap().name,
+            "apex"
+        );
+        assert_eq!(by_extension("cls", b"% comment").unwrap().name, "tex");
+        assert_eq!(by_extension("h", b"int main(void);").unwrap().name, "c");
+        assert_eq!(
+            by_extension("h", b"#include <vector>\n").unwrap().name,
+            "cpp"
+        );
+        assert_eq!(
+            by_extension("h", b"#pragma once\nnamespace a {}")
+                .unwrap()
+                .name,
+            "cpp"
+        );
+        assert_eq!(by_extension("h", b"").unwrap().name, "c");
+        assert_eq!(by_extension("pyi", b"").unwrap().name, "python");
+        assert_eq!(
+            by_syntect_name("TypeScriptReact", "ts").unwrap().name,
+            "typescript"
+        );
+        assert_eq!(
+            by_syntect_name("TypeScriptReact", "tsx").unwrap().name,
+            "tsx"
+        );
+        assert_eq!(
+            by_syntect_name("TypeScriptReact", "").unwrap().name,
+            "typescript"
+        );
+    }
+}
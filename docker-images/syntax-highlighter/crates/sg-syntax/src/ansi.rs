@@ -0,0 +1,374 @@
+//! Highlighted code for terminals, as ANSI escape sequences.
+//!
+//! Both syntect and the SCIP documents of the other engines can be rendered
+//! this way. Only the foreground color and font style of the theme are used,
+//! the terminal's own background is left alone.
+
+use std::fmt::{self, Write};
+
+use rocket::serde::json::{json, Value as JsonValue};
+use scip::types::Document;
+use serde::Deserialize;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Color, FontStyle, Style, Theme},
+    util::LinesWithEndings,
+};
+
+use crate::{
+    deadline::{Deadline, Timeout},
+    determine_language, query_document,
+    scip_html::{line_regions, ranges_by_line, ThemeStyles},
+    with_theme, DocumentStyle, HighlightError, SourcegraphQuery, SyntaxEngine, SYNTAX_SET,
+};
+
+/// The colors the terminal supports.
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsiColors {
+    /// 24-bit colors, exactly as in the theme.
+    #[default]
+    #[serde(rename = "24bit")]
+    TrueColor,
+
+    /// The xterm 256-color palette, closest to the colors of the theme.
+    #[serde(rename = "256")]
+    Ansi256,
+}
+
+impl AnsiColors {
+    /// The name clients use for these colors.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnsiColors::TrueColor => "24bit",
+            AnsiColors::Ansi256 => "256",
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct AnsiHighlightQuery {
+    // Which highlighting engine to use.
+    #[serde(default)]
+    pub engine: SyntaxEngine,
+
+    // Contents of the file
+    pub code: String,
+
+    #[serde(default)]
+    pub filepath: String,
+
+    // The language defined by the server, see SourcegraphQuery.
+    pub filetype: Option<String>,
+
+    // The name of one of the embedded themes.
+    #[serde(default)]
+    pub theme: String,
+
+    // The contents of a .tmTheme or VS Code theme file to use instead of the
+    // theme named by `theme`.
+    #[serde(default)]
+    pub custom_theme: Option<String>,
+
+    #[serde(default)]
+    pub colors: AnsiColors,
+
+    // Lines longer than this are printed without colors.
+    pub line_length_limit: Option<usize>,
+
+    // Give up on highlighting after this many milliseconds. No limit if unset.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// Highlights the code of the query with its engine and theme. The response
+/// has the same `data` and `plaintext` fields as [`syntect_highlight`](crate::syntect_highlight).
+pub fn ansi_highlight(q: AnsiHighlightQuery) -> Result<JsonValue, HighlightError> {
+    let deadline = Deadline::from_millis(q.timeout_ms);
+    let colors = q.colors;
+    let q = SourcegraphQuery {
+        engine: q.engine,
+        code: q.code,
+        filepath: q.filepath,
+        filetype: q.filetype,
+        theme: q.theme,
+        custom_theme: q.custom_theme,
+        line_length_limit: q.line_length_limit,
+        timeout_ms: q.timeout_ms,
+        ..Default::default()
+    };
+
+    if q.engine != SyntaxEngine::Syntect {
+        let document = query_document(&q, deadline)?;
+        return with_theme(&q, |theme| {
+            let output = document_ansi(
+                &document,
+                &q.code,
+                q.line_length_limit,
+                theme,
+                colors,
+                deadline,
+            )?;
+            Ok(json!({ "data": output, "plaintext": false }))
+        });
+    }
+
+    SYNTAX_SET.with(|syntax_set| {
+        let syntax_def = determine_language(&q, syntax_set)?;
+        with_theme(&q, |theme| {
+            let mut highlighter = HighlightLines::new(syntax_def, theme);
+            let mut writer = AnsiWriter::new(colors, q.code.len());
+            for line in LinesWithEndings::from(&q.code) {
+                deadline.check()?;
+                if q.line_length_limit.map_or(false, |n| line.len() > n) {
+                    writer.write_plain_line(line);
+                } else {
+                    writer.write_line(line, &highlighter.highlight(line, syntax_set));
+                }
+            }
+
+            Ok(json!({
+                "data": writer.output,
+                "plaintext": syntax_def.name == "Plain Text",
+            }))
+        })
+    })
+}
+
+/// Renders a SCIP document (e.g. from [`scip_highlight_document`](crate::scip_highlight_document))
+/// for `code` with the colors of `theme`.
+pub fn document_to_ansi(
+    document: &Document,
+    code: &str,
+    theme: &Theme,
+    colors: AnsiColors,
+) -> String {
+    document_ansi(document, code, None, theme, colors, Deadline::never())
+        .expect("rendering without a deadline can't time out")
+}
+
+fn document_ansi(
+    document: &Document,
+    code: &str,
+    max_line_len: Option<usize>,
+    theme: &Theme,
+    colors: AnsiColors,
+    deadline: Deadline,
+) -> Result<String, Timeout> {
+    let lines = LinesWithEndings::from(code).collect::<Vec<_>>();
+    let ranges = ranges_by_line(document, lines.len());
+    let mut styles = ThemeStyles::new(DocumentStyle::Theme(theme));
+    let mut writer = AnsiWriter::new(colors, code.len());
+
+    for (line, ranges) in lines.into_iter().zip(&ranges) {
+        deadline.check()?;
+        if max_line_len.map_or(false, |n| line.len() > n) {
+            writer.write_plain_line(line);
+        } else {
+            let regions = line_regions(line, ranges)
+                .into_iter()
+                .map(|(kind, text)| (styles.style(kind), text))
+                .collect::<Vec<_>>();
+            writer.write_line(line, &regions);
+        }
+    }
+
+    Ok(writer.output)
+}
+
+struct AnsiWriter {
+    colors: AnsiColors,
+    output: String,
+}
+
+impl AnsiWriter {
+    fn new(colors: AnsiColors, len: usize) -> Self {
+        Self {
+            colors,
+            output: String::with_capacity(len * 4), // size is a best guess
+        }
+    }
+
+    /// Writes a line given its styled regions, which must cover the line in
+    /// order. Every line ends with a reset, so that nothing bleeds into the
+    /// next one when lines are printed on their own.
+    fn write_line(&mut self, line: &str, regions: &[(Style, &str)]) {
+        let content = line.trim_end_matches(|c: char| c == '\n' || c == '\r');
+
+        let mut current = None;
+        let mut offset = 0;
+        for (style, text) in regions {
+            let end = (offset + text.len()).min(content.len());
+            if offset < end {
+                if current != Some(*style) {
+                    self.write_style(style);
+                    current = Some(*style);
+                }
+                write!(&mut self.output, "{}", Sanitize(&content[offset..end])).unwrap();
+            }
+            offset += text.len();
+        }
+
+        if current.is_some() {
+            self.output.push_str("\x1b[0m");
+        }
+        self.output.push_str(&line[content.len()..]);
+    }
+
+    fn write_plain_line(&mut self, line: &str) {
+        let content = line.trim_end_matches(|c: char| c == '\n' || c == '\r');
+        write!(&mut self.output, "{}", Sanitize(content)).unwrap();
+        self.output.push_str(&line[content.len()..]);
+    }
+
+    fn write_style(&mut self, style: &Style) {
+        let Color { r, g, b, .. } = style.foreground;
+        // Start from a reset, so that attributes of the previous region don't
+        // carry over.
+        match self.colors {
+            AnsiColors::TrueColor => write!(&mut self.output, "\x1b[0;38;2;{};{};{}", r, g, b),
+            AnsiColors::Ansi256 => {
+                write!(
+                    &mut self.output,
+                    "\x1b[0;38;5;{}",
+                    ansi256(style.foreground)
+                )
+            }
+        }
+        .unwrap();
+
+        if style.font_style.contains(FontStyle::BOLD) {
+            self.output.push_str(";1");
+        }
+        if style.font_style.contains(FontStyle::ITALIC) {
+            self.output.push_str(";3");
+        }
+        if style.font_style.contains(FontStyle::UNDERLINE) {
+            self.output.push_str(";4");
+        }
+        self.output.push('m');
+    }
+}
+
+/// The closest color in the xterm 256-color palette, which is the 6x6x6 color
+/// cube at 16-231 plus the 24 grays at 232-255. The first 16 colors are left
+/// out, since terminals are free to change them.
+fn ansi256(color: Color) -> u8 {
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    let Color { r, g, b, .. } = color;
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+
+    let cube_index = |v: u8| match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        v => (v - 35) / 40,
+    };
+    let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+    let cube = (
+        CUBE_LEVELS[ri as usize],
+        CUBE_LEVELS[gi as usize],
+        CUBE_LEVELS[bi as usize],
+    );
+
+    // The grays go from 8 to 238 in steps of 10.
+    let average = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+    let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + 10 * gray_index;
+
+    if distance((gray_level, gray_level, gray_level)) < distance(cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * ri + 6 * gi + bi
+    }
+}
+
+/// Shows control characters in the code in caret notation (like `cat -v`), so
+/// that the code can't send escape sequences of its own to the terminal.
+struct Sanitize<'a>(&'a str);
+
+impl<'a> fmt::Display for Sanitize<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '\t' => fmt.write_char(c)?,
+                '\x00'..='\x1f' => write!(fmt, "^{}", char::from(c as u8 ^ 0x40))?,
+                '\x7f' => fmt.write_str("^?")?,
+                // C1 control characters, e.g. CSI.
+                '\u{80}'..='\u{9f}' => fmt.write_char(char::REPLACEMENT_CHARACTER)?,
+                c => fmt.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use protobuf::EnumOrUnknown;
+    use scip::types::{Occurrence, SyntaxKind};
+
+    use super::*;
+    use crate::THEME_SET;
+
+    #[test]
+    fn syntect() {
+        let query = AnsiHighlightQuery {
+            filepath: "main.go".to_string(),
+            code: "package main\n\nfunc main() {}\n".to_string(),
+            theme: "InspiredGitHub".to_string(),
+            ..Default::default()
+        };
+        let result = ansi_highlight(query).unwrap();
+        let data = result["data"].as_str().unwrap();
+        assert!(data.starts_with("\x1b[0;38;2;"));
+        assert!(data.ends_with("}\x1b[0m\n"));
+        // Empty lines stay empty.
+        assert!(data.contains("\x1b[0m\n\n"));
+        assert_eq!(result["plaintext"], false);
+    }
+
+    #[test]
+    fn ansi256_colors() {
+        let color = |r, g, b| Color { r, g, b, a: 0xff };
+        assert_eq!(ansi256(color(0, 0, 0)), 16);
+        assert_eq!(ansi256(color(255, 0, 0)), 196);
+        assert_eq!(ansi256(color(255, 255, 255)), 231);
+        assert_eq!(ansi256(color(128, 128, 128)), 244);
+        assert_eq!(ansi256(color(0x87, 0xaf, 0xd7)), 110);
+    }
+
+    #[test]
+    fn document() {
+        let document = Document {
+            occurrences: vec![Occurrence {
+                range: vec![0, 0, 7],
+                syntax_kind: EnumOrUnknown::new(SyntaxKind::IdentifierKeyword),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let theme = &THEME_SET.themes["InspiredGitHub"];
+        let output = document_to_ansi(&document, "package foo\n", theme, AnsiColors::Ansi256);
+
+        assert!(output.starts_with("\x1b[0;38;5;"));
+        assert!(output.contains("package\x1b[0;38;5;"));
+        assert!(output.ends_with(" foo\x1b[0m\n"));
+    }
+
+    #[test]
+    fn escapes_control_characters() {
+        let query = AnsiHighlightQuery {
+            filepath: "notes.txt".to_string(),
+            code: "\x1b[31mred\tand \u{9b}\r\n".to_string(),
+            theme: "InspiredGitHub".to_string(),
+            ..Default::default()
+        };
+        let result = ansi_highlight(query).unwrap();
+        let data = result["data"].as_str().unwrap();
+        assert!(data.contains("^[[31mred\tand \u{fffd}\x1b[0m\r\n"));
+        assert_eq!(result["plaintext"], true);
+    }
+}
@@ -0,0 +1,363 @@
+//! Which occurrences changed between successive versions of one file, like an
+//! editor sends them.
+//!
+//! Every update comes with the edits that turned the last version into the
+//! new one, and only the occurrences that changed are sent back. This saves
+//! sending the whole document again, not highlighting it: tree-sitter-highlight
+//! can't start from an existing syntax tree, so every version is highlighted
+//! from scratch. Only the locals, when they are included, come from a tree
+//! that is parsed incrementally from the one of the last version.
+
+use std::{collections::HashMap, iter, ops::Range};
+
+use scip::types::{Document, Occurrence};
+use scip_syntax::languages::{get_local_configuration, LocalConfiguration};
+use scip_treesitter::types::PackedRange;
+use scip_treesitter_languages::{
+    highlights::get_highlighting_configuration, parsers::BundledParser,
+};
+use tree_sitter::{InputEdit, Parser, Tree};
+use tree_sitter_highlight::{Error, HighlightConfiguration, Highlighter as TSHighlighter};
+
+use crate::sg_treesitter::{highlight_document, merge_locals};
+
+/// The difference between two versions of a highlighted file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OccurrenceChanges {
+    /// Occurrences that are gone, with their ranges in the previous version.
+    pub removed: Vec<Occurrence>,
+    /// Occurrences that are new, with their ranges in the new version.
+    pub added: Vec<Occurrence>,
+}
+
+/// Highlights every version of a file and diffs it against the last one.
+///
+/// An occurrence that only moved because text was inserted or deleted before
+/// it is not a change.
+pub struct OccurrenceDiffer {
+    lang_config: &'static HighlightConfiguration,
+    highlighter: TSHighlighter,
+    locals: Option<Locals>,
+    code: String,
+    document: Document,
+}
+
+/// What it takes to find the locals of the next version, which is the only
+/// thing the syntax tree is needed for.
+struct Locals {
+    config: &'static LocalConfiguration,
+    parser: Parser,
+    tree: Tree,
+}
+
+impl OccurrenceDiffer {
+    /// Highlights the first version of the file, like [`crate::treesitter_index`].
+    pub fn new(filetype: &str, code: &str, include_locals: bool) -> Result<Self, Error> {
+        let lang_config = get_highlighting_configuration(filetype).ok_or(Error::InvalidLanguage)?;
+        let locals = match BundledParser::get_parser(filetype).and_then(get_local_configuration) {
+            Some(config) if include_locals => {
+                let mut parser = config.get_parser();
+                let tree = parser.parse(code, None).ok_or(Error::Unknown)?;
+                Some(Locals {
+                    config,
+                    parser,
+                    tree,
+                })
+            }
+            _ => None,
+        };
+
+        let mut differ = Self {
+            lang_config,
+            highlighter: TSHighlighter::new(),
+            locals,
+            code: code.to_string(),
+            document: Document::new(),
+        };
+        let tree = differ.locals.as_ref().map(|locals| locals.tree.clone());
+        differ.document = differ.index(code, tree.as_ref())?;
+        Ok(differ)
+    }
+
+    /// The highlighted version of the file as of the last update.
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// Moves on to the next version of the file, `code`, which is the
+    /// previous version with `edits` applied in order. On error, this stays at
+    /// the previous version.
+    pub fn update(&mut self, code: &str, edits: &[InputEdit]) -> Result<OccurrenceChanges, Error> {
+        let tree = match &mut self.locals {
+            Some(locals) => {
+                let mut old_tree = locals.tree.clone();
+                for edit in edits {
+                    old_tree.edit(edit);
+                }
+                Some(
+                    locals
+                        .parser
+                        .parse(code, Some(&old_tree))
+                        .ok_or(Error::Unknown)?,
+                )
+            }
+            None => None,
+        };
+        let document = self.index(code, tree.as_ref())?;
+
+        let changes = diff(&self.code, &self.document, code, &document, edits);
+        if let (Some(locals), Some(tree)) = (&mut self.locals, tree) {
+            locals.tree = tree;
+        }
+        self.code = code.to_string();
+        self.document = document;
+        Ok(changes)
+    }
+
+    /// Highlights `code`, and adds the locals from `tree` if there is one.
+    fn index(&mut self, code: &str, tree: Option<&Tree>) -> Result<Document, Error> {
+        // Line endings don't change the lines and columns of any node, see
+        // `index_language_with_config`.
+        let normalized = code.replace("\r\n", "\n");
+        let mut document =
+            highlight_document(&mut self.highlighter, self.lang_config, &normalized, None)?;
+
+        if let (Some(locals), Some(tree)) = (&self.locals, tree) {
+            let local_occs = scip_syntax::locals::parse_tree(locals.config, tree, code.as_bytes())
+                .unwrap_or_default();
+            merge_locals(&mut document, local_occs);
+        }
+
+        Ok(document)
+    }
+}
+
+/// What makes two occurrences the same, with the range in bytes.
+type Key = (Range<usize>, i32, String, i32);
+
+fn key(range: Range<usize>, occurrence: &Occurrence) -> Key {
+    (
+        range,
+        occurrence.syntax_kind.value(),
+        occurrence.symbol.clone(),
+        occurrence.symbol_roles,
+    )
+}
+
+fn diff(
+    old_code: &str,
+    old: &Document,
+    new_code: &str,
+    new: &Document,
+    edits: &[InputEdit],
+) -> OccurrenceChanges {
+    let old_lines = LineOffsets::new(old_code);
+    let new_lines = LineOffsets::new(new_code);
+
+    // The old occurrences are keyed by where they are in the new version.
+    let old_keys = old
+        .occurrences
+        .iter()
+        .map(|occurrence| {
+            let range = shift(old_lines.range(&occurrence.range)?, edits)?;
+            Some(key(range, occurrence))
+        })
+        .collect::<Vec<_>>();
+    let new_keys = new
+        .occurrences
+        .iter()
+        .map(|occurrence| Some(key(new_lines.range(&occurrence.range)?, occurrence)))
+        .collect::<Vec<_>>();
+
+    OccurrenceChanges {
+        removed: unmatched(&old.occurrences, &old_keys, &new_keys),
+        added: unmatched(&new.occurrences, &new_keys, &old_keys),
+    }
+}
+
+/// The `occurrences` whose keys aren't in `others`. Keys can repeat, so every
+/// key in `others` only matches one occurrence.
+fn unmatched(
+    occurrences: &[Occurrence],
+    keys: &[Option<Key>],
+    others: &[Option<Key>],
+) -> Vec<Occurrence> {
+    let mut counts = HashMap::<&Key, usize>::new();
+    for key in others.iter().flatten() {
+        *counts.entry(key).or_default() += 1;
+    }
+
+    occurrences
+        .iter()
+        .zip(keys)
+        .filter(
+            |(_, key)| match key.as_ref().and_then(|key| counts.get_mut(key)) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                }
+                _ => true,
+            },
+        )
+        .map(|(occurrence, _)| occurrence.clone())
+        .collect()
+}
+
+/// Where `range` ends up after `edits`, or `None` if one of them changed it.
+fn shift(mut range: Range<usize>, edits: &[InputEdit]) -> Option<Range<usize>> {
+    for edit in edits {
+        if range.end <= edit.start_byte {
+            continue;
+        }
+        if range.start < edit.old_end_byte {
+            return None;
+        }
+        range.start = range.start - edit.old_end_byte + edit.new_end_byte;
+        range.end = range.end - edit.old_end_byte + edit.new_end_byte;
+    }
+    Some(range)
+}
+
+/// Turns the lines and columns of occurrences back into byte offsets. Columns
+/// count characters, and never include a line's `\r`.
+struct LineOffsets<'a> {
+    code: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineOffsets<'a> {
+    fn new(code: &'a str) -> Self {
+        let starts = iter::once(0)
+            .chain(code.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { code, starts }
+    }
+
+    fn offset(&self, line: i32, col: i32) -> Option<usize> {
+        let line = usize::try_from(line).ok()?;
+        let start = *self.starts.get(line)?;
+        let end = self
+            .starts
+            .get(line + 1)
+            .map_or(self.code.len(), |next| next - 1);
+        let text = &self.code[start..end];
+
+        text.char_indices()
+            .map(|(i, _)| i)
+            .chain(iter::once(text.len()))
+            .nth(usize::try_from(col).ok()?)
+            .map(|i| start + i)
+    }
+
+    fn range(&self, range: &[i32]) -> Option<Range<usize>> {
+        let range = PackedRange::from_vec(range)?;
+        Some(
+            self.offset(range.start_line, range.start_col)?
+                ..self.offset(range.end_line, range.end_col)?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tree_sitter::Point;
+
+    use super::*;
+    use crate::treesitter_index;
+
+    fn point(code: &str, byte: usize) -> Point {
+        let before = &code[..byte];
+        let row = before.matches('\n').count();
+        let column = byte - before.rfind('\n').map_or(0, |i| i + 1);
+        Point::new(row, column)
+    }
+
+    /// Replaces `old` in `code` with `new`, returning the new code and the edit.
+    fn replace(code: &str, old: &str, new: &str) -> (String, InputEdit) {
+        let start_byte = code.find(old).unwrap();
+        let new_code = code.replacen(old, new, 1);
+        let edit = InputEdit {
+            start_byte,
+            old_end_byte: start_byte + old.len(),
+            new_end_byte: start_byte + new.len(),
+            start_position: point(code, start_byte),
+            old_end_position: point(code, start_byte + old.len()),
+            new_end_position: point(&new_code, start_byte + new.len()),
+        };
+        (new_code, edit)
+    }
+
+    const CODE: &str = "package main\n\nfunc main() {\n\tx := 1\n\tprintln(x)\n}\n";
+
+    #[test]
+    fn reports_changed_occurrences() -> Result<(), Error> {
+        let mut differ = OccurrenceDiffer::new("go", CODE, false)?;
+        assert_eq!(differ.document(), &treesitter_index("go", CODE, false)?);
+
+        let (code, edit) = replace(CODE, "1", "2");
+        assert_eq!(differ.update(&code, &[edit])?, OccurrenceChanges::default());
+
+        let (code, edit) = replace(&code, "2", "\"two\"");
+        let changes = differ.update(&code, &[edit])?;
+        assert_eq!(changes.removed.len(), 1);
+        assert_eq!(changes.removed[0].range, vec![3, 6, 7]);
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.added[0].range, vec![3, 6, 11]);
+        assert_eq!(differ.document(), &treesitter_index("go", &code, false)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_moved_occurrences() -> Result<(), Error> {
+        let mut differ = OccurrenceDiffer::new("go", CODE, false)?;
+
+        let (code, first) = replace(CODE, "\n\n", "\n\n// main\n");
+        let (code, second) = replace(&code, "\tprintln(x)\n", "");
+        let changes = differ.update(&code, &[first, second])?;
+        assert_eq!(
+            changes
+                .added
+                .iter()
+                .map(|occurrence| occurrence.range.clone())
+                .collect::<Vec<_>>(),
+            vec![vec![2, 0, 7]]
+        );
+        assert!(changes
+            .removed
+            .iter()
+            .all(|occurrence| occurrence.range[0] == 4));
+        assert!(!changes.removed.is_empty());
+        assert_eq!(differ.document(), &treesitter_index("go", &code, false)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_incrementally_only_for_locals() -> Result<(), Error> {
+        let differ = OccurrenceDiffer::new("go", CODE, false)?;
+        assert!(differ.locals.is_none());
+
+        let mut differ = OccurrenceDiffer::new("go", CODE, true)?;
+        assert_eq!(differ.document(), &treesitter_index("go", CODE, true)?);
+
+        let (code, edit) = replace(CODE, "\tprintln(x)\n", "\tprintln(x)\n\tprintln(x)\n");
+        let changes = differ.update(&code, &[edit])?;
+        assert!(!changes.added.is_empty());
+        assert_eq!(differ.document(), &treesitter_index("go", &code, true)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_crlf_columns() -> Result<(), Error> {
+        let code = CODE.replace('\n', "\r\n");
+        let mut differ = OccurrenceDiffer::new("go", &code, false)?;
+
+        let (new_code, edit) = replace(&code, "\r\n\r\n", "\r\n\r\n\r\n");
+        let changes = differ.update(&new_code, &[edit])?;
+        assert_eq!(changes, OccurrenceChanges::default());
+
+        Ok(())
+    }
+}
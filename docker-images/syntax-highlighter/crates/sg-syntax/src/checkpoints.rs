@@ -0,0 +1,216 @@
+//! Snapshots of syntect's parser state, so that highlighting a window of a
+//! file doesn't have to parse everything before the window again.
+//!
+//! While parsing, the state at the start of every [`CHECKPOINT_INTERVAL`]th
+//! line is saved, keyed by a hash of the syntax and of all the lines before
+//! it. A later request can then start from the last checkpoint before its
+//! window. Since the key only covers the lines before the checkpoint, this
+//! also works for an edited version of the file, as long as the edit comes
+//! after the checkpoint.
+
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use syntect::{
+    parsing::{ParseState, ScopeStack, SyntaxReference},
+    util::LinesWithEndings,
+};
+
+/// Lines between checkpoints. Parsing this many lines is cheap compared to
+/// parsing a whole large file, and it keeps the number of checkpoints low.
+pub(crate) const CHECKPOINT_INTERVAL: usize = 256;
+
+// A checkpoint is the parser's stack of contexts plus the scope stack, which
+// are small. Every thread has its own copy of the syntax set, but they are all
+// loaded from the same dump, so the states are interchangeable between them.
+const CHECKPOINT_CACHE_SIZE: usize = 4096;
+
+type Key = [u8; 32];
+
+lazy_static::lazy_static! {
+    static ref CHECKPOINTS: Mutex<LruCache<Key, Arc<Checkpoint>>> = Mutex::new(LruCache::new(
+        NonZeroUsize::new(CHECKPOINT_CACHE_SIZE).unwrap()
+    ));
+}
+
+/// The state of the parser at the start of a line.
+pub(crate) struct Checkpoint {
+    pub(crate) parse_state: ParseState,
+    pub(crate) stack: ScopeStack,
+}
+
+/// Where to continue parsing from, see [`CheckpointKeys::restore`].
+pub(crate) struct Resume {
+    /// The first line that still has to be parsed.
+    pub(crate) line: usize,
+    pub(crate) checkpoint: Arc<Checkpoint>,
+    /// The keys as of `line`, to keep saving checkpoints after it.
+    pub(crate) keys: CheckpointKeys,
+}
+
+/// Computes the keys of the checkpoints of a file as its lines go by.
+#[derive(Clone)]
+pub(crate) struct CheckpointKeys {
+    hasher: Sha256,
+    line: usize,
+}
+
+impl CheckpointKeys {
+    /// Lines longer than `max_line_len` aren't parsed, which changes the
+    /// state after them, so it is part of the key.
+    pub(crate) fn new(syntax: &SyntaxReference, max_line_len: Option<usize>) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(syntax.name.as_bytes());
+        hasher.update([0]);
+        hasher.update(max_line_len.map_or(u64::MAX, |n| n as u64).to_le_bytes());
+        Self { hasher, line: 0 }
+    }
+
+    /// The key for the state before the next line, if it gets a checkpoint.
+    pub(crate) fn key(&self) -> Option<Key> {
+        if self.line > 0 && self.line % CHECKPOINT_INTERVAL == 0 {
+            Some(self.hasher.clone().finalize().into())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn push_line(&mut self, line: &str) {
+        self.hasher.update(line.as_bytes());
+        self.line += 1;
+    }
+
+    /// Finds the last checkpoint of `code` at or before `line`, if any. The
+    /// keys must not have seen any lines yet.
+    pub(crate) fn restore(&self, code: &str, line: usize) -> Option<Resume> {
+        if line < CHECKPOINT_INTERVAL {
+            return None;
+        }
+
+        let mut keys = self.clone();
+        let mut resume = None;
+        for (i, contents) in LinesWithEndings::from(code).enumerate().take(line + 1) {
+            if let Some(key) = keys.key() {
+                if let Some(checkpoint) = CHECKPOINTS.lock().unwrap().get(&key) {
+                    resume = Some(Resume {
+                        line: i,
+                        checkpoint: checkpoint.clone(),
+                        keys: keys.clone(),
+                    });
+                }
+            }
+            keys.push_line(contents);
+        }
+        resume
+    }
+}
+
+/// Remembers the state before the line that `key` belongs to.
+pub(crate) fn save(key: Key, parse_state: &ParseState, stack: &ScopeStack) {
+    let mut checkpoints = CHECKPOINTS.lock().unwrap();
+    if !checkpoints.contains(&key) {
+        checkpoints.put(
+            key,
+            Arc::new(Checkpoint {
+                parse_state: parse_state.clone(),
+                stack: stack.clone(),
+            }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use syntect::{html::ClassStyle, parsing::SyntaxSet};
+
+    use super::*;
+    use crate::{sg_sciptect::DocumentGenerator, sg_syntect::ClassedTableGenerator};
+
+    // Every test uses its own code, since the checkpoints are shared.
+    fn code(name: &str) -> String {
+        let mut code = format!("package {}\n", name);
+        for i in 1..600 {
+            match i {
+                300 => code.push_str("/*\n"),
+                550 => code.push_str("*/\n"),
+                i => code.push_str(&format!("var x{} = {}\n", i, i)),
+            }
+        }
+        code
+    }
+
+    fn generate(syntax_set: &SyntaxSet, code: &str, lines: std::ops::Range<usize>) -> String {
+        let syntax_def = syntax_set.find_syntax_by_extension("go").unwrap();
+        ClassedTableGenerator::new(
+            syntax_set,
+            syntax_def,
+            code,
+            None,
+            ClassStyle::SpacedPrefixed { prefix: "hl-" },
+        )
+        .with_lines(lines)
+        .generate()
+    }
+
+    fn restored_line(syntax_set: &SyntaxSet, code: &str, line: usize) -> Option<usize> {
+        let syntax_def = syntax_set.find_syntax_by_extension("go").unwrap();
+        CheckpointKeys::new(syntax_def, None)
+            .restore(code, line)
+            .map(|resume| resume.line)
+    }
+
+    #[test]
+    fn resumes_from_checkpoints() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let code = code("resumes");
+        assert_eq!(restored_line(&syntax_set, &code, 520), None);
+
+        let whole_file = generate(&syntax_set, &code, 0..usize::MAX);
+        assert_eq!(restored_line(&syntax_set, &code, 520), Some(512));
+        assert_eq!(restored_line(&syntax_set, &code, 511), Some(256));
+
+        // The window is still inside of the comment that started on line 300.
+        let window = generate(&syntax_set, &code, 520..521);
+        let row = whole_file
+            .split("<tr>")
+            .find(|row| row.contains("data-line=\"521\""))
+            .unwrap();
+        assert_eq!(window, format!("<table><tbody><tr>{}</tbody></table>", row));
+        assert!(window.contains("hl-comment"));
+    }
+
+    #[test]
+    fn resumes_documents() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let syntax_def = syntax_set.find_syntax_by_extension("go").unwrap();
+        let code = code("documents");
+        generate(&syntax_set, &code, 0..usize::MAX);
+
+        let document = DocumentGenerator::new(&syntax_set, syntax_def, &code, None)
+            .with_lines(520..521)
+            .generate();
+        let ranges = document
+            .occurrences
+            .iter()
+            .map(|occurrence| occurrence.range.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec![vec![520, 0, 15]]);
+    }
+
+    #[test]
+    fn resumes_after_edits() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let code = code("edits");
+        generate(&syntax_set, &code, 0..usize::MAX);
+
+        let edited_after = code.replacen("var x580 ", "var y580 ", 1);
+        assert_eq!(restored_line(&syntax_set, &edited_after, 520), Some(512));
+
+        let edited_before = code.replacen("var x10 ", "var y10 ", 1);
+        assert_eq!(restored_line(&syntax_set, &edited_before, 520), None);
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    cmp::{self, Reverse},
+    collections::BinaryHeap,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex, Weak,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+
+/// Point in time after which highlighting a file should be abandoned.
+///
+/// Some syntect grammars backtrack catastrophically on particular inputs, which
+/// can pin a CPU for minutes. The deadline is checked between lines for syntect
+/// and through the cancellation flag for tree-sitter.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    /// A deadline that never expires.
+    pub fn never() -> Self {
+        Self(None)
+    }
+
+    pub fn after(timeout: Duration) -> Self {
+        Self(Instant::now().checked_add(timeout))
+    }
+
+    /// Creates a deadline from an optional timeout in milliseconds, as passed
+    /// in queries. `None` means no deadline.
+    pub fn from_millis(timeout_ms: Option<u64>) -> Self {
+        match timeout_ms {
+            Some(ms) => Self::after(Duration::from_millis(ms)),
+            None => Self::never(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.0.map_or(false, |deadline| Instant::now() >= deadline)
+    }
+
+    pub fn check(&self) -> Result<(), Timeout> {
+        if self.is_expired() {
+            Err(Timeout)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns a flag that becomes non-zero once the deadline has passed, which
+    /// is the form of cancellation tree-sitter understands.
+    pub fn cancellation_flag(&self) -> CancellationFlag {
+        let flag = Arc::new(AtomicUsize::new(0));
+        if let Some(deadline) = self.0 {
+            WATCHDOG.watch(deadline, &flag);
+        }
+        CancellationFlag { flag }
+    }
+}
+
+/// See [`Deadline::cancellation_flag`].
+pub struct CancellationFlag {
+    flag: Arc<AtomicUsize>,
+}
+
+impl CancellationFlag {
+    pub fn as_atomic(&self) -> &AtomicUsize {
+        &self.flag
+    }
+}
+
+static WATCHDOG: Lazy<Watchdog> = Lazy::new(Watchdog::start);
+
+/// The one thread that sets the cancellation flags of all deadlines, so that
+/// highlighting many files at once doesn't start a thread for every file.
+struct Watchdog {
+    sender: Mutex<mpsc::Sender<(Instant, Weak<AtomicUsize>)>>,
+}
+
+impl Watchdog {
+    fn start() -> Self {
+        let (sender, receiver) = mpsc::channel::<(Instant, Weak<AtomicUsize>)>();
+        thread::Builder::new()
+            .name("deadline-watchdog".to_string())
+            .spawn(move || {
+                let mut pending = BinaryHeap::new();
+                loop {
+                    let next = pending
+                        .peek()
+                        .map(|Reverse(Watched(deadline, _))| *deadline);
+                    let received = match next {
+                        Some(deadline) => receiver
+                            .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                        None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    };
+                    match received {
+                        Ok((deadline, flag)) => pending.push(Reverse(Watched(deadline, flag))),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+
+                    let now = Instant::now();
+                    while let Some(Reverse(Watched(deadline, _))) = pending.peek() {
+                        if *deadline > now {
+                            break;
+                        }
+                        let Reverse(Watched(_, flag)) = pending.pop().unwrap();
+                        // The flag is gone if highlighting finished in time.
+                        if let Some(flag) = flag.upgrade() {
+                            flag.store(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            })
+            .expect("failed to start the deadline watchdog");
+
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    fn watch(&self, deadline: Instant, flag: &Arc<AtomicUsize>) {
+        let sender = self.sender.lock().unwrap_or_else(|err| err.into_inner());
+        // Sending only fails if the watchdog thread died, and then the flag
+        // never gets set. Callers check `is_expired` afterwards anyway.
+        let _ = sender.send((deadline, Arc::downgrade(flag)));
+    }
+}
+
+/// A flag waiting for its deadline, ordered by the deadline alone.
+struct Watched(Instant, Weak<AtomicUsize>);
+
+impl PartialEq for Watched {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Watched {}
+
+impl PartialOrd for Watched {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Watched {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+/// Returned when a [`Deadline`] passes before highlighting finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out while highlighting code")
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn passed() -> Deadline {
+        Deadline(Instant::now().checked_sub(Duration::from_secs(1)))
+    }
+
+    fn distant() -> Deadline {
+        Deadline::after(Duration::from_secs(3600))
+    }
+
+    fn is_set(flag: &CancellationFlag) -> bool {
+        flag.as_atomic().load(Ordering::Relaxed) != 0
+    }
+
+    /// Waits for the watchdog to set `flag`, for far longer than it should
+    /// ever take so that a busy machine doesn't fail the test.
+    fn wait_until_set(flag: &CancellationFlag) -> bool {
+        let start = Instant::now();
+        while !is_set(flag) {
+            if start.elapsed() > Duration::from_secs(10) {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    #[test]
+    fn never_expires() {
+        let deadline = Deadline::never();
+        assert!(!deadline.is_expired());
+        assert_eq!(deadline.check(), Ok(()));
+        assert!(!is_set(&deadline.cancellation_flag()));
+    }
+
+    #[test]
+    fn expires_once_passed() {
+        assert!(passed().is_expired());
+        assert_eq!(passed().check(), Err(Timeout));
+
+        assert!(!distant().is_expired());
+        assert_eq!(distant().check(), Ok(()));
+    }
+
+    #[test]
+    fn sets_cancellation_flag_once_expired() {
+        let flag = passed().cancellation_flag();
+        assert!(wait_until_set(&flag));
+    }
+
+    #[test]
+    fn sets_cancellation_flags_in_deadline_order() {
+        let later = distant().cancellation_flag();
+        let never = Deadline::never().cancellation_flag();
+        let sooner = passed().cancellation_flag();
+
+        assert!(wait_until_set(&sooner));
+        assert!(!is_set(&later));
+        assert!(!is_set(&never));
+    }
+}
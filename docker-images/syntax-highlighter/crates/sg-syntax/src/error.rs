@@ -0,0 +1,90 @@
+use std::fmt;
+
+use rocket::serde::json::{json, Value as JsonValue};
+
+use crate::deadline::Timeout;
+
+/// Every way highlighting a file can fail.
+///
+/// Clients get these as `{"error": "<message>", "code": "<code>"}`, where the
+/// code (see [`HighlightError::code`]) is stable and meant to be matched on,
+/// while the message is only meant for humans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HighlightError {
+    /// We don't have a highlighter for the requested language, or couldn't
+    /// figure out the language of the file.
+    UnknownLanguage(String),
+    /// The requested theme does not exist.
+    InvalidTheme(String),
+    /// The custom theme sent along with the query could not be parsed.
+    InvalidCustomTheme(String),
+    /// The query is missing a field or has an invalid value.
+    InvalidRequest(String),
+    /// The file could not be parsed.
+    ParseFailed(String),
+    /// Highlighting took longer than the query allowed.
+    Timeout,
+    /// The file is too large to be highlighted.
+    TooLarge(String),
+    /// Highlighting panicked.
+    Panic,
+    /// Anything else, which is a bug on our side.
+    Internal(String),
+}
+
+impl HighlightError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            HighlightError::UnknownLanguage(_) => "unknown_language",
+            HighlightError::InvalidTheme(_) => "invalid_theme",
+            HighlightError::InvalidCustomTheme(_) => "invalid_custom_theme",
+            HighlightError::InvalidRequest(_) => "invalid_request",
+            HighlightError::ParseFailed(_) => "parse_failed",
+            HighlightError::Timeout => "timeout",
+            HighlightError::TooLarge(_) => "too_large",
+            HighlightError::Panic => "panic",
+            HighlightError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({"error": self.to_string(), "code": self.code()})
+    }
+
+    pub(crate) fn from_treesitter(err: tree_sitter_highlight::Error, filetype: &str) -> Self {
+        match err {
+            tree_sitter_highlight::Error::InvalidLanguage => HighlightError::UnknownLanguage(
+                format!("{} is not a valid filetype for treesitter", filetype),
+            ),
+            tree_sitter_highlight::Error::Cancelled => HighlightError::Timeout,
+            tree_sitter_highlight::Error::Unknown => {
+                HighlightError::ParseFailed(format!("failed to parse {} file", filetype))
+            }
+        }
+    }
+}
+
+impl fmt::Display for HighlightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HighlightError::UnknownLanguage(message)
+            | HighlightError::InvalidCustomTheme(message)
+            | HighlightError::InvalidRequest(message)
+            | HighlightError::ParseFailed(message)
+            | HighlightError::TooLarge(message)
+            | HighlightError::Internal(message) => write!(f, "{}", message),
+            // Kept as is for clients that still compare error messages.
+            HighlightError::InvalidTheme(_) => write!(f, "invalid theme"),
+            HighlightError::Timeout => write!(f, "{}", Timeout),
+            HighlightError::Panic => write!(f, "panic while highlighting code"),
+        }
+    }
+}
+
+impl std::error::Error for HighlightError {}
+
+impl From<Timeout> for HighlightError {
+    fn from(_: Timeout) -> Self {
+        HighlightError::Timeout
+    }
+}
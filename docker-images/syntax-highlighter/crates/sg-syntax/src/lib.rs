@@ -1,26 +1,61 @@
-use std::path::Path;
+use std::{collections::HashSet, ops::Range, path::Path};
 
 use protobuf::Message;
 use rocket::serde::json::{json, Value as JsonValue};
-use serde::Deserialize;
-use sg_treesitter::jsonify_err;
+use scip::types::Document;
+use scip_treesitter_languages::{detection::detect_language, registry};
+use serde::{Deserialize, Serialize};
 use syntect::{
-    highlighting::ThemeSet,
-    html::{highlighted_html_for_string, ClassStyle},
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    html::{
+        start_highlighted_html_snippet, styled_line_to_highlighted_html, ClassStyle,
+        IncludeBackground,
+    },
     parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
 };
 
+mod deadline;
+pub use deadline::{Deadline, Timeout};
+
+mod error;
+pub use error::HighlightError;
+
+mod limits;
+pub use limits::{
+    ansi_highlight_with_limits, scip_highlight_document_with_limits, syntect_highlight_with_limits,
+    Degradation, Limits,
+};
+
+mod themes;
+
+mod window;
+
+mod checkpoints;
+
+mod scip_html;
+pub use scip_html::{DocumentStyle, DocumentTableGenerator};
+
+mod ansi;
+pub use ansi::{ansi_highlight, document_to_ansi, AnsiColors, AnsiHighlightQuery};
+
 mod sg_treesitter;
 pub use sg_treesitter::{
     index_language as treesitter_index, index_language_with_config as treesitter_index_with_config,
-    lsif_highlight,
+    index_language_with_deadline as treesitter_index_with_deadline, lsif_highlight,
 };
 
+mod changes;
+pub use changes::{OccurrenceChanges, OccurrenceDiffer};
+
 mod sg_syntect;
 use sg_syntect::ClassedTableGenerator;
-use tree_sitter_highlight::Error;
 
-use crate::sg_treesitter::treesitter_language;
+use crate::{
+    sg_treesitter::treesitter_language,
+    window::{clip_document, line_window},
+};
 
 mod sg_sciptect;
 
@@ -65,11 +100,33 @@ pub struct SourcegraphQuery {
     pub line_length_limit: Option<usize>,
 
     // theme is ignored if css is true
+    #[serde(default)]
     pub theme: String,
+
+    // The contents of a .tmTheme or VS Code theme file to use instead of the
+    // theme named by `theme`. Ignored if css is true.
+    #[serde(default)]
+    pub custom_theme: Option<String>,
+
+    // Give up on highlighting after this many milliseconds. No limit if unset.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    // The engine that highlights the code. Engines other than syntect produce
+    // the same HTML from their SCIP document, see DocumentTableGenerator.
+    #[serde(default)]
+    pub engine: SyntaxEngine,
+
+    // Only highlight these lines, zero-based and with an exclusive end. The
+    // lines before start_line are still parsed. Unset means the whole file.
+    #[serde(default)]
+    pub start_line: Option<usize>,
+    #[serde(default)]
+    pub end_line: Option<usize>,
 }
 
 // NOTE: Keep in sync: internal/gosyntect/gosyntect.go
-#[derive(Deserialize, Default, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SyntaxEngine {
     #[default]
     #[serde(rename = "syntect")]
@@ -82,6 +139,33 @@ pub enum SyntaxEngine {
     ScipSyntax,
 }
 
+impl SyntaxEngine {
+    /// The name clients use for this engine.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyntaxEngine::Syntect => "syntect",
+            SyntaxEngine::TreeSitter => "tree-sitter",
+            SyntaxEngine::ScipSyntax => "scip-syntax",
+        }
+    }
+}
+
+impl std::str::FromStr for SyntaxEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "syntect" => Ok(SyntaxEngine::Syntect),
+            "tree-sitter" => Ok(SyntaxEngine::TreeSitter),
+            "scip-syntax" => Ok(SyntaxEngine::ScipSyntax),
+            _ => Err(format!(
+                "unknown engine {:?}, expected syntect, tree-sitter or scip-syntax",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Deserialize, Default, Debug)]
 pub struct ScipHighlightQuery {
     // Which highlighting engine to use.
@@ -100,6 +184,17 @@ pub struct ScipHighlightQuery {
     // line_length_limit is used to limit syntect problems when
     // parsing very long lines
     pub line_length_limit: Option<usize>,
+
+    // Give up on highlighting after this many milliseconds. No limit if unset.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    // Only highlight these lines, zero-based and with an exclusive end. The
+    // lines before start_line are still parsed. Unset means the whole file.
+    #[serde(default)]
+    pub start_line: Option<usize>,
+    #[serde(default)]
+    pub end_line: Option<usize>,
 }
 
 pub fn determine_filetype(q: &SourcegraphQuery) -> String {
@@ -108,36 +203,56 @@ pub fn determine_filetype(q: &SourcegraphQuery) -> String {
         Err(_) => "".to_owned(),
     });
 
+    let extension = match q.extension.as_str() {
+        "" => Path::new(&q.filepath)
+            .extension()
+            .and_then(|x| x.to_str())
+            .unwrap_or(""),
+        extension => extension,
+    };
+
+    // Languages that only tree-sitter knows about.
     if filetype.is_empty() || filetype.to_lowercase() == "plain text" {
-        #[allow(clippy::single_match)]
-        match q.extension.as_str() {
-            "ncl" => return "nickel".to_string(),
-            _ => {}
-        };
+        if let Some(language) = registry::by_extension(extension, q.code.as_bytes()) {
+            if language.syntect.is_empty() {
+                return language.name.to_string();
+            }
+        }
     }
 
     // Normalize all the filenames here
-    match filetype.as_str() {
-        "Rust Enhanced" => "rust",
-        "C++" => "cpp",
-        "C#" => "c_sharp",
-        "JS Custom - React" => "javascript",
-        "TypeScriptReact" => {
-            if q.filepath.ends_with(".tsx") {
-                "tsx"
-            } else {
-                "typescript"
-            }
+    match registry::by_syntect_name(&filetype, extension) {
+        Some(language) => language.name.to_string(),
+        None => filetype.to_lowercase(),
+    }
+}
+
+/// Like [`determine_filetype`], but only looks at the path and filetype, never
+/// at the contents of the file. The result is always one of the languages we
+/// know about (or "plain text"), which makes it safe to use as a metrics label.
+pub fn language_name(filepath: &str, filetype: Option<&str>) -> String {
+    if let Some(language) = filetype.and_then(registry::by_name) {
+        if language.capabilities.highlights {
+            return language.name.to_string();
         }
-        filetype => filetype,
     }
-    .to_lowercase()
+
+    let filetype = determine_filetype(&SourcegraphQuery {
+        filepath: filepath.to_string(),
+        filetype: filetype.map(str::to_lowercase),
+        ..Default::default()
+    });
+    if filetype.is_empty() {
+        "plain text".to_string()
+    } else {
+        filetype
+    }
 }
 
 pub fn determine_language<'a>(
     q: &SourcegraphQuery,
     syntax_set: &'a SyntaxSet,
-) -> Result<&'a SyntaxReference, JsonValue> {
+) -> Result<&'a SyntaxReference, HighlightError> {
     // If filetype is passed, we should choose that if possible.
     if let Some(filetype) = &q.filetype {
         // This is `find_syntax_by_name` except that it doesn't care about
@@ -162,7 +277,9 @@ pub fn determine_language<'a>(
             // Fall back: Determine syntax definition by first line.
             None => match syntax_set.find_syntax_by_first_line(&q.code) {
                 Some(v) => Ok(v),
-                None => Err(json!({"error": "invalid extension"})),
+                None => Err(HighlightError::UnknownLanguage(
+                    "invalid extension".to_string(),
+                )),
             },
         };
     }
@@ -175,40 +292,14 @@ pub fn determine_language<'a>(
 
     // Override syntect's language detection for conflicting file extensions because
     // it's impossible to express this logic in a syntax definition.
-    struct Override {
-        extension: &'static str,
-        prefix_langs: Vec<(&'static str, &'static str)>,
-        default: &'static str,
-    }
-    let overrides = vec![
-        Override {
-            extension: "cls",
-            prefix_langs: vec![("%", "TeX"), ("\\", "TeX")],
-            default: "Apex",
-        },
-        Override {
-            extension: "xlsg",
-            prefix_langs: vec![],
-            default: "xlsg",
-        },
-    ];
-
-    if let Some(Override {
-        prefix_langs,
-        default,
-        ..
-    }) = overrides.iter().find(|o| o.extension == extension)
-    {
-        let name = match prefix_langs
-            .iter()
-            .find(|(prefix, _)| q.code.starts_with(prefix))
-        {
-            Some((_, lang)) => lang,
-            None => default,
-        };
-        return Ok(syntax_set
-            .find_syntax_by_name(name)
-            .unwrap_or_else(|| syntax_set.find_syntax_plain_text()));
+    if let Some(language) = registry::by_extension(extension, q.code.as_bytes()) {
+        if language.override_syntect {
+            return Ok(language
+                .syntect
+                .iter()
+                .find_map(|name| syntax_set.find_syntax_by_name(name))
+                .unwrap_or_else(|| syntax_set.find_syntax_plain_text()));
+        }
     }
 
     Ok(syntax_set
@@ -242,13 +333,83 @@ pub fn list_features() {
     });
 }
 
-pub fn syntect_highlight(q: SourcegraphQuery) -> JsonValue {
+/// Every language from the registry, plus the ones only syntect knows about,
+/// with their extensions and what the tree-sitter engines can do with them.
+pub fn list_languages() -> JsonValue {
+    SYNTAX_SET.with(|syntax_set| {
+        let mut claimed = HashSet::new();
+        let mut languages = vec![];
+        for language in registry::LANGUAGES {
+            // Syntaxes that only differ from the registry's name by case are
+            // the same language, see `determine_filetype`.
+            let syntaxes = syntax_set
+                .syntaxes()
+                .iter()
+                .filter(|syntax| {
+                    language.syntect.contains(&syntax.name.as_str())
+                        || syntax.name.to_lowercase() == language.name
+                })
+                .collect::<Vec<_>>();
+
+            let mut extensions = language
+                .extensions
+                .iter()
+                .chain(language.shared_extensions)
+                .map(|extension| extension.to_string())
+                .collect::<Vec<_>>();
+            for syntax in &syntaxes {
+                claimed.insert(syntax.name.as_str());
+                for extension in &syntax.file_extensions {
+                    if !extensions.contains(extension) {
+                        extensions.push(extension.clone());
+                    }
+                }
+            }
+
+            let capabilities = language.capabilities;
+            languages.push(json!({
+                "name": language.name,
+                "aliases": language.aliases,
+                "extensions": extensions,
+                "filenames": language.filenames,
+                "syntect": syntaxes.iter().map(|syntax| &syntax.name).collect::<Vec<_>>(),
+                "tree_sitter": {
+                    "highlights": capabilities.highlights,
+                    "tags": capabilities.tags,
+                    "locals": capabilities.locals,
+                },
+            }));
+        }
+
+        for syntax in syntax_set.syntaxes() {
+            if claimed.contains(syntax.name.as_str()) {
+                continue;
+            }
+            languages.push(json!({
+                "name": syntax.name.to_lowercase(),
+                "aliases": [],
+                "extensions": syntax.file_extensions,
+                "filenames": [],
+                "syntect": [syntax.name],
+                "tree_sitter": {"highlights": false, "tags": false, "locals": false},
+            }));
+        }
+
+        languages.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        json!({ "languages": languages })
+    })
+}
+
+pub fn syntect_highlight(q: SourcegraphQuery) -> Result<JsonValue, HighlightError> {
+    let deadline = Deadline::from_millis(q.timeout_ms);
+    let lines = line_window(q.start_line, q.end_line)?;
+    if q.engine != SyntaxEngine::Syntect {
+        return document_highlight(q, lines, deadline);
+    }
+
     SYNTAX_SET.with(|syntax_set| {
         // Determine syntax definition by extension.
-        let syntax_def = match determine_language(&q, syntax_set) {
-            Ok(v) => v,
-            Err(e) => return e,
-        };
+        let syntax_def = determine_language(&q, syntax_set)?;
 
         if q.css {
             let output = ClassedTableGenerator::new(
@@ -258,31 +419,146 @@ pub fn syntect_highlight(q: SourcegraphQuery) -> JsonValue {
                 q.line_length_limit,
                 ClassStyle::SpacedPrefixed { prefix: "hl-" },
             )
-            .generate();
+            .with_lines(lines)
+            .generate_with_deadline(deadline)?;
 
-            json!({ "data": output, "plaintext": syntax_def.name == "Plain Text", })
+            Ok(json!({ "data": output, "plaintext": syntax_def.name == "Plain Text", }))
         } else {
-            // TODO(slimsag): return the theme's background color (and other info??) to caller?
-            // https://github.com/trishume/syntect/blob/c8b47758a3872d478c7fc740782cd468b2c0a96b/examples/synhtml.rs#L24
-
-            // Determine theme to use.
-            //
-            // TODO(slimsag): We could let the query specify the theme file's actual
-            // bytes? e.g. via `load_from_reader`.
-            let theme = match THEME_SET.themes.get(&q.theme) {
-                Some(v) => v,
-                None => return json!({"error": "invalid theme", "code": "invalid_theme"}),
-            };
-
-            json!({
-                "data": highlighted_html_for_string(&q.code, syntax_set, syntax_def, theme),
-                "plaintext": syntax_def.name == "Plain Text",
+            with_theme(&q, |theme| {
+                let output =
+                    highlighted_html(&q.code, syntax_set, syntax_def, theme, lines, deadline)?;
+
+                Ok(json!({
+                    "data": output,
+                    "plaintext": syntax_def.name == "Plain Text",
+                    "theme": themes::theme_metadata(theme),
+                }))
             })
         }
     })
 }
 
-pub fn scip_highlight(q: ScipHighlightQuery) -> Result<JsonValue, JsonValue> {
+/// The HTML output of [`syntect_highlight`] for the tree-sitter and
+/// scip-syntax engines, rendered from their SCIP document.
+fn document_highlight(
+    q: SourcegraphQuery,
+    lines: Range<usize>,
+    deadline: Deadline,
+) -> Result<JsonValue, HighlightError> {
+    let document = query_document(&q, deadline)?;
+
+    let generate = |style: DocumentStyle| {
+        DocumentTableGenerator::new(&document, &q.code, q.line_length_limit, style)
+            .with_lines(lines.clone())
+            .generate_with_deadline(deadline)
+    };
+
+    if q.css {
+        let output = generate(DocumentStyle::Classes { prefix: "hl-" })?;
+
+        Ok(json!({ "data": output, "plaintext": false }))
+    } else {
+        with_theme(&q, |theme| {
+            let output = generate(DocumentStyle::Theme(theme))?;
+
+            Ok(json!({
+                "data": output,
+                "plaintext": false,
+                "theme": themes::theme_metadata(theme),
+            }))
+        })
+    }
+}
+
+/// The SCIP document that the query's engine produces for its code. The
+/// caller's `deadline` covers the rendering of the document too.
+fn query_document(q: &SourcegraphQuery, deadline: Deadline) -> Result<Document, HighlightError> {
+    highlight_document_with_deadline(
+        ScipHighlightQuery {
+            engine: q.engine,
+            code: q.code.clone(),
+            filepath: q.filepath.clone(),
+            // tree-sitter needs a filetype, so fall back to the one for the path.
+            filetype: q
+                .filetype
+                .clone()
+                .or_else(|| Some(language_name(&q.filepath, None))),
+            line_length_limit: q.line_length_limit,
+            timeout_ms: q.timeout_ms,
+            start_line: q.start_line,
+            end_line: q.end_line,
+        },
+        deadline,
+    )
+}
+
+/// Calls `f` with the query's custom theme, or else the embedded theme it
+/// names.
+fn with_theme<T>(
+    q: &SourcegraphQuery,
+    f: impl FnOnce(&Theme) -> Result<T, HighlightError>,
+) -> Result<T, HighlightError> {
+    match &q.custom_theme {
+        Some(contents) => f(&themes::custom_theme(contents)?),
+        None => f(THEME_SET
+            .themes
+            .get(&q.theme)
+            .ok_or_else(|| HighlightError::InvalidTheme(q.theme.clone()))?),
+    }
+}
+
+/// Same as syntect's `highlighted_html_for_string`, except that it only
+/// outputs `lines` and gives up once `deadline` has passed.
+fn highlighted_html(
+    code: &str,
+    syntax_set: &SyntaxSet,
+    syntax_def: &SyntaxReference,
+    theme: &Theme,
+    lines: Range<usize>,
+    deadline: Deadline,
+) -> Result<String, Timeout> {
+    let mut highlighter = HighlightLines::new(syntax_def, theme);
+    let (mut output, bg) = start_highlighted_html_snippet(theme);
+    for (i, line) in LinesWithEndings::from(code).enumerate().take(lines.end) {
+        deadline.check()?;
+
+        // The lines before the window still have to go through the
+        // highlighter, so that it is in the right state when it starts.
+        let regions = highlighter.highlight(line, syntax_set);
+        if i < lines.start {
+            continue;
+        }
+        output.push_str(&styled_line_to_highlighted_html(
+            &regions[..],
+            IncludeBackground::IfDifferent(bg),
+        ));
+    }
+    output.push_str("</pre>\n");
+    Ok(output)
+}
+
+pub fn scip_highlight(q: ScipHighlightQuery) -> Result<JsonValue, HighlightError> {
+    let document = scip_highlight_document(q)?;
+    let encoded = document
+        .write_to_bytes()
+        .map_err(|err| HighlightError::Internal(err.to_string()))?;
+    Ok(json!({"scip": base64::encode(encoded), "plaintext": false}))
+}
+
+/// Same as [`scip_highlight`], but returns the SCIP document itself instead of
+/// its base64-encoded JSON representation.
+pub fn scip_highlight_document(q: ScipHighlightQuery) -> Result<Document, HighlightError> {
+    let deadline = Deadline::from_millis(q.timeout_ms);
+    highlight_document_with_deadline(q, deadline)
+}
+
+/// Same as [`scip_highlight_document`], but ignores `q.timeout_ms` in favour
+/// of a deadline the caller already started.
+fn highlight_document_with_deadline(
+    q: ScipHighlightQuery,
+    deadline: Deadline,
+) -> Result<Document, HighlightError> {
+    let lines = line_window(q.start_line, q.end_line)?;
     match q.engine {
         SyntaxEngine::Syntect => SYNTAX_SET.with(|ss| {
             let sg_query = SourcegraphQuery {
@@ -292,39 +568,52 @@ pub fn scip_highlight(q: ScipHighlightQuery) -> Result<JsonValue, JsonValue> {
                 css: true,
                 line_length_limit: None,
                 theme: Default::default(),
+                custom_theme: None,
+                engine: SyntaxEngine::Syntect,
+                start_line: None,
+                end_line: None,
                 code: q.code.clone(),
+                timeout_ms: q.timeout_ms,
             };
 
-            let language = determine_language(&sg_query, ss).map_err(jsonify_err)?;
-            let document = sg_sciptect::DocumentGenerator::new(
+            let language = determine_language(&sg_query, ss)?;
+            Ok(sg_sciptect::DocumentGenerator::new(
                 ss,
                 language,
                 q.code.as_str(),
                 q.line_length_limit,
             )
-            .generate();
-            let encoded = document.write_to_bytes().map_err(jsonify_err)?;
-            Ok(json!({"scip": base64::encode(encoded), "plaintext": false}))
+            .with_lines(lines)
+            .generate_with_deadline(deadline)?)
         }),
         SyntaxEngine::TreeSitter | SyntaxEngine::ScipSyntax => {
-            let language = q
-                .filetype
-                .ok_or_else(|| json!({"error": "Must pass a language for /scip" }))?
-                .to_lowercase();
+            let language = match q.filetype {
+                Some(filetype) => filetype.to_lowercase(),
+                None => detect_language(&q.filepath, q.code.as_bytes())
+                    .ok_or_else(|| {
+                        HighlightError::UnknownLanguage(
+                            "Could not detect the language of the file, pass a filetype"
+                                .to_string(),
+                        )
+                    })?
+                    .parser
+                    .get_language_name()
+                    .to_string(),
+            };
 
             let include_locals = q.engine == SyntaxEngine::ScipSyntax;
 
-            match treesitter_index(treesitter_language(&language), &q.code, include_locals) {
-                Ok(document) => {
-                    let encoded = document.write_to_bytes().map_err(jsonify_err)?;
-
-                    Ok(json!({"scip": base64::encode(encoded), "plaintext": false}))
-                }
-                Err(Error::InvalidLanguage) => Err(json!({
-                    "error": format!("{} is not a valid filetype for treesitter", language)
-                })),
-                Err(err) => Err(jsonify_err(err)),
-            }
+            // tree-sitter always parses the whole file, so this only saves
+            // on the size of the response.
+            let mut document = treesitter_index_with_deadline(
+                treesitter_language(&language),
+                &q.code,
+                include_locals,
+                deadline,
+            )
+            .map_err(|err| HighlightError::from_treesitter(err, &language))?;
+            clip_document(&mut document, &lines);
+            Ok(document)
         }
     }
 }
@@ -346,6 +635,11 @@ mod tests {
             line_length_limit: None,
             extension: String::new(),
             theme: String::new(),
+            custom_theme: None,
+            engine: SyntaxEngine::Syntect,
+            start_line: None,
+            end_line: None,
+            timeout_ms: None,
         };
         let result = determine_language(&query, &syntax_set);
         assert_eq!(result.unwrap().name, "TeX");
@@ -362,8 +656,112 @@ mod tests {
             line_length_limit: None,
             extension: String::new(),
             theme: String::new(),
+            custom_theme: None,
+            engine: SyntaxEngine::Syntect,
+            start_line: None,
+            end_line: None,
+            timeout_ms: None,
         };
         let result = determine_language(&query, &syntax_set);
         assert_eq!(result.unwrap().name, "Apex");
     }
+
+    #[test]
+    fn h_c_or_cpp() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        for (code, syntax, filetype) in [
+            ("int main(void);", "C", "c"),
+            ("#include <vector>\n", "C++", "cpp"),
+        ] {
+            let query = SourcegraphQuery {
+                filepath: "foo.h".to_string(),
+                code: code.to_string(),
+                ..Default::default()
+            };
+            let result = determine_language(&query, &syntax_set);
+            assert_eq!(result.unwrap().name, syntax);
+            // tree-sitter gets the same language.
+            assert_eq!(determine_filetype(&query), filetype);
+        }
+    }
+
+    #[test]
+    fn unknown_treesitter_language() {
+        let query = ScipHighlightQuery {
+            engine: SyntaxEngine::TreeSitter,
+            filetype: Some("not-a-language".to_string()),
+            code: "".to_string(),
+            ..Default::default()
+        };
+        let err = scip_highlight_document(query).unwrap_err();
+        assert_eq!(err.code(), "unknown_language");
+    }
+
+    #[test]
+    fn theme_metadata() {
+        let query = SourcegraphQuery {
+            filepath: "foo.go".to_string(),
+            code: "package main".to_string(),
+            theme: "Solarized (dark)".to_string(),
+            ..Default::default()
+        };
+        let result = syntect_highlight(query).unwrap();
+        assert_eq!(result["theme"]["name"], "Solarized (dark)");
+        assert_eq!(result["theme"]["kind"], "dark");
+        assert!(result["theme"]["background"].is_string());
+    }
+
+    #[test]
+    fn invalid_theme() {
+        let query = SourcegraphQuery {
+            filepath: "foo.go".to_string(),
+            code: "package main".to_string(),
+            theme: "not-a-theme".to_string(),
+            ..Default::default()
+        };
+        let err = syntect_highlight(query).unwrap_err();
+        assert_eq!(err, HighlightError::InvalidTheme("not-a-theme".to_string()));
+        assert_eq!(err.to_json()["code"], "invalid_theme");
+    }
+
+    #[test]
+    fn custom_theme() {
+        let query = SourcegraphQuery {
+            filepath: "foo.go".to_string(),
+            code: "package main".to_string(),
+            custom_theme: Some(
+                r##"{"tokenColors": [{"scope": "keyword", "settings": {"foreground": "#ff0000"}}]}"##
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        let result = syntect_highlight(query).unwrap();
+        assert!(result["data"].as_str().unwrap().contains("color:#ff0000;"));
+        assert_eq!(result["theme"]["kind"], "light");
+
+        let query = SourcegraphQuery {
+            filepath: "foo.go".to_string(),
+            code: "package main".to_string(),
+            custom_theme: Some("{".to_string()),
+            ..Default::default()
+        };
+        let err = syntect_highlight(query).unwrap_err();
+        assert_eq!(err.code(), "invalid_custom_theme");
+        assert!(err.to_string().starts_with("invalid VS Code theme"));
+    }
+
+    #[test]
+    fn treesitter_html() {
+        let query = SourcegraphQuery {
+            filepath: "foo.go".to_string(),
+            code: "package main\n".to_string(),
+            css: true,
+            engine: SyntaxEngine::TreeSitter,
+            ..Default::default()
+        };
+        let result = syntect_highlight(query).unwrap();
+        let data = result["data"].as_str().unwrap();
+        assert!(data.starts_with("<table>"));
+        assert!(data.contains("<span class=\"hl-typed-IdentifierKeyword\">package</span>"));
+    }
 }
@@ -0,0 +1,263 @@
+use rocket::serde::json::{json, Value as JsonValue};
+use scip::types::Document;
+use scip_treesitter_languages::highlights::get_highlighting_configuration;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ansi_highlight, language_name, scip_highlight_document, sg_treesitter::treesitter_language,
+    syntect_highlight, AnsiHighlightQuery, HighlightError, ScipHighlightQuery, SourcegraphQuery,
+    SyntaxEngine,
+};
+
+/// Upper bounds on the size of files we are willing to highlight properly.
+///
+/// Files over any of these limits get a cheaper fallback instead (see
+/// [`Degradation`]), since highlighting them can take long enough to tie up a
+/// worker, with syntect in particular. `None` means no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub max_bytes: Option<usize>,
+    pub max_lines: Option<usize>,
+    pub max_line_length: Option<usize>,
+}
+
+impl Limits {
+    /// Returns which limit `code` exceeds, if any.
+    pub fn exceeded_by(&self, code: &str) -> Option<String> {
+        if let Some(max_bytes) = self.max_bytes {
+            if code.len() > max_bytes {
+                return Some(format!("file is larger than {} bytes", max_bytes));
+            }
+        }
+
+        if self.max_lines.is_none() && self.max_line_length.is_none() {
+            return None;
+        }
+
+        let mut lines = 0;
+        for line in code.lines() {
+            lines += 1;
+            if let Some(max_line_length) = self.max_line_length {
+                if line.len() > max_line_length {
+                    return Some(format!(
+                        "file has lines longer than {} bytes",
+                        max_line_length
+                    ));
+                }
+            }
+        }
+
+        match self.max_lines {
+            Some(max_lines) if lines > max_lines => {
+                Some(format!("file has more than {} lines", max_lines))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns how `q` would be degraded under these limits, if at all.
+    pub fn scip_degradation(&self, q: &ScipHighlightQuery) -> Option<Degradation> {
+        self.exceeded_by(&q.code)?;
+
+        // Tree-sitter is what we fall back to, so there is nothing cheaper to
+        // do other than not highlighting at all.
+        if q.engine == SyntaxEngine::TreeSitter {
+            return Some(Degradation::Plaintext);
+        }
+
+        match treesitter_fallback(q) {
+            Some(_) => Some(Degradation::TreeSitter),
+            None => Some(Degradation::Plaintext),
+        }
+    }
+}
+
+/// How a file over the [`Limits`] was highlighted instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Degradation {
+    /// Highlighted with tree-sitter only, without syntect or locals.
+    TreeSitter,
+    /// Not highlighted at all.
+    Plaintext,
+}
+
+impl Degradation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Degradation::TreeSitter => "tree-sitter",
+            Degradation::Plaintext => "plaintext",
+        }
+    }
+}
+
+/// Same as [`scip_highlight_document`], except that files over `limits` are
+/// highlighted as described by [`Limits::scip_degradation`].
+pub fn scip_highlight_document_with_limits(
+    q: ScipHighlightQuery,
+    limits: &Limits,
+) -> Result<Document, HighlightError> {
+    match limits.scip_degradation(&q) {
+        None => scip_highlight_document(q),
+        Some(Degradation::TreeSitter) => {
+            let filetype = treesitter_fallback(&q);
+            scip_highlight_document(ScipHighlightQuery {
+                engine: SyntaxEngine::TreeSitter,
+                filetype,
+                ..q
+            })
+        }
+        Some(Degradation::Plaintext) => Ok(Document::default()),
+    }
+}
+
+/// Same as [`syntect_highlight`], except that files over `limits` are
+/// rendered as plain text. The response then has a `degraded` field.
+pub fn syntect_highlight_with_limits(
+    mut q: SourcegraphQuery,
+    limits: &Limits,
+) -> Result<JsonValue, HighlightError> {
+    if limits.exceeded_by(&q.code).is_none() {
+        return syntect_highlight(q);
+    }
+
+    // Plain text is only cheap with syntect, whichever engine was asked for.
+    q.engine = SyntaxEngine::Syntect;
+    q.filetype = Some("plain text".to_string());
+    let mut response = syntect_highlight(q)?;
+    response["degraded"] = json!(Degradation::Plaintext.as_str());
+    Ok(response)
+}
+
+/// Same as [`ansi_highlight`], except that files over `limits` are printed
+/// without colors. The response then has a `degraded` field.
+pub fn ansi_highlight_with_limits(
+    mut q: AnsiHighlightQuery,
+    limits: &Limits,
+) -> Result<JsonValue, HighlightError> {
+    if limits.exceeded_by(&q.code).is_none() {
+        return ansi_highlight(q);
+    }
+
+    q.engine = SyntaxEngine::Syntect;
+    q.filetype = Some("plain text".to_string());
+    let mut response = ansi_highlight(q)?;
+    response["degraded"] = json!(Degradation::Plaintext.as_str());
+    Ok(response)
+}
+
+/// The tree-sitter filetype to use for `q`, if tree-sitter supports it.
+fn treesitter_fallback(q: &ScipHighlightQuery) -> Option<String> {
+    let filetype = language_name(&q.filepath, q.filetype.as_deref());
+    get_highlighting_configuration(treesitter_language(&filetype)).map(|_| filetype)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exceeded_by() {
+        let limits = Limits {
+            max_bytes: Some(20),
+            max_lines: Some(2),
+            max_line_length: Some(5),
+        };
+
+        assert_eq!(limits.exceeded_by("a\nb"), None);
+        assert!(limits.exceeded_by("a\nb\nc").is_some());
+        assert!(limits.exceeded_by("abcdef").is_some());
+        assert!(limits.exceeded_by(&"a\n".repeat(11)).is_some());
+        assert_eq!(Limits::default().exceeded_by(&"a".repeat(10_000)), None);
+    }
+
+    #[test]
+    fn falls_back_to_treesitter() {
+        let limits = Limits {
+            max_lines: Some(1),
+            ..Default::default()
+        };
+        let query = ScipHighlightQuery {
+            engine: SyntaxEngine::Syntect,
+            filepath: "main.go".to_string(),
+            code: "package main\n\nfunc main() {}\n".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            limits.scip_degradation(&query),
+            Some(Degradation::TreeSitter)
+        );
+        let document = scip_highlight_document_with_limits(query, &limits).unwrap();
+        assert!(!document.occurrences.is_empty());
+    }
+
+    #[test]
+    fn falls_back_to_plaintext() {
+        let limits = Limits {
+            max_bytes: Some(1),
+            ..Default::default()
+        };
+        let query = ScipHighlightQuery {
+            engine: SyntaxEngine::Syntect,
+            filepath: "notes.unknown-extension".to_string(),
+            code: "hello".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            limits.scip_degradation(&query),
+            Some(Degradation::Plaintext)
+        );
+        let document = scip_highlight_document_with_limits(query, &limits).unwrap();
+        assert_eq!(document, Document::default());
+    }
+
+    #[test]
+    fn does_not_highlight_large_files_with_treesitter() {
+        let query = || ScipHighlightQuery {
+            engine: SyntaxEngine::TreeSitter,
+            filetype: Some("go".to_string()),
+            code: "package main\n\nfunc main() {}\n".to_string(),
+            ..Default::default()
+        };
+
+        for limits in [
+            Limits {
+                max_bytes: Some(10),
+                ..Default::default()
+            },
+            Limits {
+                max_lines: Some(2),
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(
+                limits.scip_degradation(&query()),
+                Some(Degradation::Plaintext)
+            );
+            let document = scip_highlight_document_with_limits(query(), &limits).unwrap();
+            assert_eq!(document, Document::default());
+        }
+
+        assert_eq!(Limits::default().scip_degradation(&query()), None);
+    }
+
+    #[test]
+    fn renders_large_files_as_plain_text() {
+        let limits = Limits {
+            max_bytes: Some(1),
+            ..Default::default()
+        };
+        let query = SourcegraphQuery {
+            filepath: "main.go".to_string(),
+            code: "package main\n".to_string(),
+            css: true,
+            ..Default::default()
+        };
+
+        let response = syntect_highlight_with_limits(query, &limits).unwrap();
+        assert_eq!(response["plaintext"], true);
+        assert_eq!(response["degraded"], "plaintext");
+    }
+}
@@ -0,0 +1,378 @@
+use std::{cmp::Reverse, collections::HashMap, fmt::Write, ops::Range};
+
+use scip::types::{Document, SyntaxKind};
+use scip_treesitter::types::PackedRange;
+use syntect::{
+    highlighting::{Color, Highlighter, Style, Theme},
+    html::{styled_line_to_highlighted_html, IncludeBackground},
+    parsing::Scope,
+    util::LinesWithEndings,
+};
+
+use crate::{
+    deadline::{Deadline, Timeout},
+    sg_syntect::{close_row, close_table, open_row, open_table, Escape},
+};
+
+/// How [`DocumentTableGenerator`] styles highlighted ranges.
+#[derive(Clone, Copy)]
+pub enum DocumentStyle<'a> {
+    /// `<span class="{prefix}typed-{SyntaxKind}">`, e.g.
+    /// `hl-typed-IdentifierKeyword`.
+    Classes { prefix: &'a str },
+    /// Inline colors from the theme, like syntect's own HTML output.
+    Theme(&'a Theme),
+}
+
+/// The DocumentTableGenerator renders a SCIP document for `code` as the same
+/// HTML table as [`ClassedTableGenerator`](crate::sg_syntect::ClassedTableGenerator),
+/// so that every engine can back the HTML output.
+///
+/// Occurrences without a syntax kind are ignored. Where occurrences overlap,
+/// the innermost one wins.
+///
+/// If max_line_len is not None, any lines with length greater than the
+/// provided number will not be highlighted.
+pub struct DocumentTableGenerator<'a> {
+    document: &'a Document,
+    code: &'a str,
+    max_line_len: Option<usize>,
+    style: DocumentStyle<'a>,
+    lines: Range<usize>,
+    html: String,
+}
+
+impl<'a> DocumentTableGenerator<'a> {
+    pub fn new(
+        document: &'a Document,
+        code: &'a str,
+        max_line_len: Option<usize>,
+        style: DocumentStyle<'a>,
+    ) -> Self {
+        Self {
+            document,
+            code,
+            max_line_len,
+            style,
+            lines: 0..usize::MAX,
+            html: String::with_capacity(code.len() * 8), // size is a best guess
+        }
+    }
+
+    /// Only generate rows for `lines` (zero-based, end exclusive), like
+    /// [`ClassedTableGenerator::with_lines`](crate::sg_syntect::ClassedTableGenerator::with_lines).
+    pub fn with_lines(mut self, lines: Range<usize>) -> Self {
+        self.lines = lines;
+        self
+    }
+
+    // generate takes ownership of self so that it can't be re-used
+    pub fn generate(self) -> String {
+        self.generate_with_deadline(Deadline::never())
+            .expect("generation without a deadline can't time out")
+    }
+
+    /// Like [`generate`](Self::generate), but gives up once `deadline` has
+    /// passed. The deadline is checked before every line.
+    pub fn generate_with_deadline(mut self, deadline: Deadline) -> Result<String, Timeout> {
+        let lines = LinesWithEndings::from(self.code).collect::<Vec<_>>();
+        let ranges = ranges_by_line(self.document, lines.len());
+        let mut styles = ThemeStyles::new(self.style);
+
+        open_table(&mut self.html);
+        for (i, line) in lines
+            .into_iter()
+            .enumerate()
+            .take(self.lines.end)
+            .skip(self.lines.start)
+        {
+            deadline.check()?;
+            open_row(&mut self.html, i);
+            if self.max_line_len.map_or(false, |n| line.len() > n) {
+                write!(&mut self.html, "{}", Escape(line)).unwrap();
+            } else {
+                self.write_line(line, &ranges[i], &mut styles);
+            }
+            close_row(&mut self.html);
+        }
+        close_table(&mut self.html);
+
+        Ok(self.html)
+    }
+
+    fn write_line(&mut self, line: &str, ranges: &[LineRange], styles: &mut ThemeStyles) {
+        let regions = line_regions(line, ranges);
+        match self.style {
+            DocumentStyle::Classes { prefix } => {
+                for (kind, text) in regions {
+                    match kind {
+                        Some(kind) => write!(
+                            &mut self.html,
+                            "<span class=\"{}typed-{:?}\">{}</span>",
+                            prefix,
+                            kind,
+                            Escape(text)
+                        ),
+                        None => write!(&mut self.html, "{}", Escape(text)),
+                    }
+                    .unwrap();
+                }
+            }
+            DocumentStyle::Theme(_) => {
+                let regions = regions
+                    .into_iter()
+                    .map(|(kind, text)| (styles.style(kind), text))
+                    .collect::<Vec<_>>();
+                self.html.push_str(&styled_line_to_highlighted_html(
+                    &regions,
+                    IncludeBackground::IfDifferent(styles.background),
+                ));
+            }
+        }
+    }
+}
+
+/// The part of an occurrence that falls on a single line, in characters.
+pub(crate) struct LineRange {
+    start: usize,
+    end: usize,
+    kind: SyntaxKind,
+}
+
+/// Splits the occurrences of `document` into ranges per line, ordered so that
+/// occurrences nested in another one come after it.
+pub(crate) fn ranges_by_line(document: &Document, line_count: usize) -> Vec<Vec<LineRange>> {
+    let mut lines = (0..line_count).map(|_| vec![]).collect::<Vec<_>>();
+    for occurrence in &document.occurrences {
+        let kind = occurrence.syntax_kind.enum_value_or_default();
+        if kind == SyntaxKind::UnspecifiedSyntaxKind {
+            continue;
+        }
+        let range = match PackedRange::from_vec(&occurrence.range) {
+            Some(range) if range.start_line >= 0 && range.start_line <= range.end_line => range,
+            _ => continue,
+        };
+        if range.start_line as usize >= line_count {
+            continue;
+        }
+
+        let last_line = (range.end_line as usize).min(line_count - 1);
+        for line in range.start_line as usize..=last_line {
+            let start = if line == range.start_line as usize {
+                range.start_col.max(0) as usize
+            } else {
+                0
+            };
+            let end = if line == range.end_line as usize {
+                range.end_col.max(0) as usize
+            } else {
+                usize::MAX
+            };
+            lines[line].push(LineRange { start, end, kind });
+        }
+    }
+
+    for ranges in &mut lines {
+        ranges.sort_by_key(|range| (range.start, Reverse(range.end)));
+    }
+    lines
+}
+
+/// Splits `line` into consecutive regions of the same syntax kind, given the
+/// ranges on that line from [`ranges_by_line`].
+pub(crate) fn line_regions<'l>(
+    line: &'l str,
+    ranges: &[LineRange],
+) -> Vec<(Option<SyntaxKind>, &'l str)> {
+    // Columns are counted in characters, so paint the kind of every
+    // character, innermost occurrences last.
+    let offsets = line
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .collect::<Vec<_>>();
+    let mut kinds = vec![None; offsets.len()];
+    for range in ranges {
+        let end = range.end.min(kinds.len());
+        if range.start < end {
+            kinds[range.start..end].fill(Some(range.kind));
+        }
+    }
+
+    let mut regions = vec![];
+    let mut start = 0;
+    while start < kinds.len() {
+        let kind = kinds[start];
+        let end = (start..kinds.len())
+            .find(|&i| kinds[i] != kind)
+            .unwrap_or(kinds.len());
+        let end_offset = offsets.get(end).copied().unwrap_or(line.len());
+        regions.push((kind, &line[offsets[start]..end_offset]));
+        start = end;
+    }
+    regions
+}
+
+/// Looks up (and remembers) the theme's style for every syntax kind.
+pub(crate) struct ThemeStyles<'a> {
+    highlighter: Option<Highlighter<'a>>,
+    styles: HashMap<Option<SyntaxKind>, Style>,
+    background: Color,
+}
+
+impl<'a> ThemeStyles<'a> {
+    pub(crate) fn new(style: DocumentStyle<'a>) -> Self {
+        let (highlighter, background) = match style {
+            DocumentStyle::Classes { .. } => (None, Color::WHITE),
+            DocumentStyle::Theme(theme) => (
+                Some(Highlighter::new(theme)),
+                theme.settings.background.unwrap_or(Color::WHITE),
+            ),
+        };
+
+        Self {
+            highlighter,
+            styles: HashMap::new(),
+            background,
+        }
+    }
+
+    pub(crate) fn style(&mut self, kind: Option<SyntaxKind>) -> Style {
+        let highlighter = self
+            .highlighter
+            .as_ref()
+            .expect("styles are only used with a theme");
+        *self
+            .styles
+            .entry(kind)
+            .or_insert_with(|| match kind.and_then(textmate_scope) {
+                Some(scope) => highlighter.style_for_stack(&[scope]),
+                None => highlighter.get_default(),
+            })
+    }
+}
+
+/// The TextMate scope themes are most likely to have a color for, roughly the
+/// reverse of the mapping in `sg_sciptect`.
+fn textmate_scope(kind: SyntaxKind) -> Option<Scope> {
+    use SyntaxKind::*;
+
+    let scope = match kind {
+        Comment => "comment",
+        PunctuationDelimiter => "punctuation.separator",
+        PunctuationBracket => "punctuation.section",
+        IdentifierKeyword => "keyword",
+        IdentifierOperator => "keyword.operator",
+        Identifier => "variable",
+        IdentifierBuiltin => "support.function",
+        IdentifierNull => "constant.language.null",
+        IdentifierConstant => "constant.other",
+        IdentifierParameter => "variable.parameter",
+        IdentifierNamespace => "entity.name.namespace",
+        IdentifierFunction => "entity.name.function",
+        IdentifierType => "entity.name.type",
+        IdentifierBuiltinType => "support.type",
+        IdentifierAttribute | TagAttribute => "entity.other.attribute-name",
+        RegexEscape => "constant.character.escape",
+        RegexRepeated | RegexWildcard | RegexDelimiter | RegexJoin => "string.regexp",
+        StringLiteral | StringLiteralKey => "string",
+        StringLiteralEscape => "constant.character.escape",
+        CharacterLiteral => "constant.character",
+        NumericLiteral => "constant.numeric",
+        BooleanLiteral => "constant.language",
+        _ => return None,
+    };
+    Scope::new(scope).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use protobuf::EnumOrUnknown;
+    use scip::types::Occurrence;
+
+    use super::*;
+    use crate::THEME_SET;
+
+    fn occurrence(range: Vec<i32>, kind: SyntaxKind) -> Occurrence {
+        Occurrence {
+            range,
+            syntax_kind: EnumOrUnknown::new(kind),
+            ..Default::default()
+        }
+    }
+
+    fn document() -> Document {
+        Document {
+            occurrences: vec![
+                occurrence(vec![0, 0, 7], SyntaxKind::IdentifierKeyword),
+                // Nested occurrences win over the outer ones.
+                occurrence(vec![1, 0, 2, 2], SyntaxKind::StringLiteral),
+                occurrence(vec![1, 2, 4], SyntaxKind::StringLiteralEscape),
+                // Locals don't have a syntax kind.
+                occurrence(vec![0, 8, 11], SyntaxKind::UnspecifiedSyntaxKind),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn classes() {
+        let code = "package foo\n\"a\\n<\nb\" x\n";
+        let document = document();
+        let html = DocumentTableGenerator::new(
+            &document,
+            code,
+            None,
+            DocumentStyle::Classes { prefix: "hl-" },
+        )
+        .generate();
+
+        assert_eq!(
+            html,
+            concat!(
+                "<table><tbody>",
+                "<tr><td class=\"line\" data-line=\"1\"/><td class=\"code\"><div>",
+                "<span class=\"hl-typed-IdentifierKeyword\">package</span> foo\n",
+                "</div></td></tr>",
+                "<tr><td class=\"line\" data-line=\"2\"/><td class=\"code\"><div>",
+                "<span class=\"hl-typed-StringLiteral\">&quot;a</span>",
+                "<span class=\"hl-typed-StringLiteralEscape\">\\n</span>",
+                "<span class=\"hl-typed-StringLiteral\">&lt;\n</span>",
+                "</div></td></tr>",
+                "<tr><td class=\"line\" data-line=\"3\"/><td class=\"code\"><div>",
+                "<span class=\"hl-typed-StringLiteral\">b&quot;</span> x\n",
+                "</div></td></tr>",
+                "</tbody></table>",
+            )
+        );
+    }
+
+    #[test]
+    fn long_lines() {
+        let code = "package foo\n";
+        let document = document();
+        let html = DocumentTableGenerator::new(
+            &document,
+            code,
+            Some(5),
+            DocumentStyle::Classes { prefix: "hl-" },
+        )
+        .generate();
+
+        assert!(!html.contains("<span"));
+    }
+
+    #[test]
+    fn theme() {
+        let code = "package foo\n";
+        let document = document();
+        let theme = &THEME_SET.themes["InspiredGitHub"];
+        let html = DocumentTableGenerator::new(&document, code, None, DocumentStyle::Theme(theme))
+            .generate();
+
+        let keyword = Highlighter::new(theme).style_for_stack(&[Scope::new("keyword").unwrap()]);
+        let Color { r, g, b, .. } = keyword.foreground;
+        let color = format!("color:#{:02x}{:02x}{:02x};", r, g, b);
+        let start = html.find(&color).expect("keywords should be colored");
+        assert!(html[start..].contains(">package</span>"));
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashSet, fmt::Debug};
+use std::{collections::HashSet, fmt::Debug, ops::Range};
 
 use once_cell::sync::OnceCell;
 use protobuf::EnumOrUnknown;
@@ -8,6 +8,12 @@ use syntect::{
     util::LinesWithEndings,
 };
 
+use crate::{
+    checkpoints::{self, CheckpointKeys},
+    deadline::{Deadline, Timeout},
+    window::clip_document,
+};
+
 static EMPTY_SCOPE: OnceCell<Scope> = OnceCell::new();
 fn empty_scope() -> Scope {
     *EMPTY_SCOPE.get_or_init(|| Scope::new("").unwrap())
@@ -196,6 +202,8 @@ pub struct DocumentGenerator<'a> {
     parse_state: ParseState,
     code: &'a str,
     max_line_len: Option<usize>,
+    lines: Range<usize>,
+    checkpoint_keys: CheckpointKeys,
 }
 
 #[derive(Clone)]
@@ -342,17 +350,72 @@ impl<'a> DocumentGenerator<'a> {
             syntax_set: ss,
             parse_state: ParseState::new(sr),
             max_line_len,
+            lines: 0..usize::MAX,
+            checkpoint_keys: CheckpointKeys::new(sr, max_line_len),
         }
     }
 
+    /// Only generate occurrences for `lines` (zero-based, end exclusive). The
+    /// lines before them are still parsed, so that highlighting starts in the
+    /// right state, e.g. inside of a block comment. Occurrences that cross
+    /// the edges of the window are cut off there.
+    pub fn with_lines(mut self, lines: Range<usize>) -> Self {
+        self.lines = lines;
+        self
+    }
+
     // generate takes ownership of self so that it can't be re-used
-    pub fn generate(mut self) -> Document {
+    pub fn generate(self) -> Document {
+        self.generate_with_deadline(Deadline::never())
+            .expect("generation without a deadline can't time out")
+    }
+
+    /// Like [`generate`](Self::generate), but gives up once `deadline` has
+    /// passed. The deadline is checked before every line.
+    pub fn generate_with_deadline(mut self, deadline: Deadline) -> Result<Document, Timeout> {
         let mut document = Document::default();
 
         let mut stack = ScopeStack::new();
         let mut unhandled_scopes = HashSet::new();
         let mut highlight_manager = HighlightManager::default();
-        for (row, line_contents) in LinesWithEndings::from(self.code).enumerate() {
+
+        let (start, mut checkpoint_keys) =
+            match self.checkpoint_keys.restore(self.code, self.lines.start) {
+                Some(resume) => {
+                    self.parse_state = resume.checkpoint.parse_state.clone();
+                    stack = resume.checkpoint.stack.clone();
+                    // The scopes that are still open all start before the
+                    // window, so start them at the checkpoint. They get cut
+                    // off at the start of the window anyway.
+                    for scope in stack.as_slice() {
+                        match match_scope_to_kind(scope) {
+                            Some(kind) if !should_skip_scope(scope) => {
+                                highlight_manager.push_hl(HighlightStart::some(
+                                    resume.line,
+                                    0,
+                                    kind,
+                                    *scope,
+                                ));
+                            }
+                            _ => highlight_manager.push_empty(),
+                        }
+                    }
+                    (resume.line, resume.keys)
+                }
+                None => (0, self.checkpoint_keys.clone()),
+            };
+
+        for (row, line_contents) in LinesWithEndings::from(self.code)
+            .enumerate()
+            .take(self.lines.end)
+            .skip(start)
+        {
+            deadline.check()?;
+            if let Some(key) = checkpoint_keys.key() {
+                checkpoints::save(key, &self.parse_state, &stack);
+            }
+            checkpoint_keys.push_line(line_contents);
+
             // Do not attempt to parse very long lines
             if self.max_line_len.map_or(false, |n| line_contents.len() > n) {
                 continue;
@@ -432,8 +495,9 @@ impl<'a> DocumentGenerator<'a> {
 
         // Only panic in test code, this condition should only result
         // in one line not being highlighted correctly, so we can just
-        // continue on in production.
-        if cfg!(test) {
+        // continue on in production. Highlights are expected to still be
+        // open when the window ends before the file does.
+        if cfg!(test) && self.lines.end == usize::MAX {
             if highlight_manager
                 .highlights
                 .iter()
@@ -455,6 +519,7 @@ impl<'a> DocumentGenerator<'a> {
         // then we need to close them with the range that is the very end of the contents
         if let Some(end_of_line) = LinesWithEndings::from(self.code)
             .enumerate()
+            .take(self.lines.end)
             .last()
             .map(|(row, line)| (row, line.chars().count()))
         {
@@ -469,7 +534,8 @@ impl<'a> DocumentGenerator<'a> {
             }
         }
 
-        document
+        clip_document(&mut document, &self.lines);
+        Ok(document)
     }
 }
 
@@ -553,7 +619,7 @@ mod test {
     }
 
     use super::*;
-    use crate::{determine_language, SourcegraphQuery};
+    use crate::{determine_language, SourcegraphQuery, SyntaxEngine};
 
     #[test]
     fn test_generates_empty_file() {
@@ -571,6 +637,30 @@ mod test {
         assert_eq!(Document::default(), output);
     }
 
+    #[test]
+    fn test_line_window() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let q = crate::SourcegraphQuery {
+            filetype: Some("go".to_string()),
+            code: "package main\n/*\ncomment\n*/\nvar x = 1\n".to_string(),
+            ..Default::default()
+        };
+
+        let syntax_def = determine_language(&q, &syntax_set).unwrap();
+        let output = DocumentGenerator::new(&syntax_set, syntax_def, &q.code, None)
+            .with_lines(2..3)
+            .generate();
+
+        // The comment starts before the window and ends after it, so it is cut
+        // off at both ends.
+        assert_eq!(output.occurrences.len(), 1);
+        assert_eq!(output.occurrences[0].range, vec![2, 0, 8]);
+        assert_eq!(
+            output.occurrences[0].syntax_kind.enum_value_or_default(),
+            SyntaxKind::Comment
+        );
+    }
+
     #[test]
     fn test_all_files() -> Result<(), std::io::Error> {
         let ss = SyntaxSet::load_defaults_newlines();
@@ -607,7 +697,12 @@ mod test {
                 css: false,
                 line_length_limit: None,
                 theme: "".to_string(),
+                custom_theme: None,
+                engine: SyntaxEngine::Syntect,
+                start_line: None,
+                end_line: None,
                 code: contents.clone(),
+                timeout_ms: None,
             };
             let syntax_def = determine_language(&q, &ss).unwrap();
             let document = DocumentGenerator::new(&ss, syntax_def, &q.code, None).generate();
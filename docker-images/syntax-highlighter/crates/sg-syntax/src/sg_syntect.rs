@@ -1,4 +1,5 @@
-use std::fmt::Write;
+use std::{fmt::Write, ops::Range};
+
 use syntect::{
     html::ClassStyle,
     parsing::{
@@ -8,6 +9,11 @@ use syntect::{
     util::LinesWithEndings,
 };
 
+use crate::{
+    checkpoints::{self, CheckpointKeys},
+    deadline::{Deadline, Timeout},
+};
+
 /// The ClassedTableGenerator generates HTML tables of the following form:
 /// <table>
 ///   <tbody>
@@ -33,6 +39,8 @@ pub struct ClassedTableGenerator<'a> {
     style: ClassStyle,
     code: &'a str,
     max_line_len: Option<usize>,
+    lines: Range<usize>,
+    checkpoint_keys: CheckpointKeys,
 }
 
 impl<'a> ClassedTableGenerator<'a> {
@@ -51,16 +59,62 @@ impl<'a> ClassedTableGenerator<'a> {
             html: String::with_capacity(code.len() * 8), // size is a best guess
             style,
             max_line_len,
+            lines: 0..usize::MAX,
+            checkpoint_keys: CheckpointKeys::new(sr, max_line_len),
         }
     }
 
+    /// Only generate rows for `lines` (zero-based, end exclusive). The lines
+    /// before them are still parsed, or picked up from a checkpoint of an
+    /// earlier request, so that highlighting starts in the right state, e.g.
+    /// inside of a block comment. Rows keep their line numbers.
+    pub fn with_lines(mut self, lines: Range<usize>) -> Self {
+        self.lines = lines;
+        self
+    }
+
     // generate takes ownership of self so that it can't be re-used
-    pub fn generate(mut self) -> String {
+    pub fn generate(self) -> String {
+        self.generate_with_deadline(Deadline::never())
+            .expect("generation without a deadline can't time out")
+    }
+
+    /// Like [`generate`](Self::generate), but gives up once `deadline` has
+    /// passed. The deadline is checked before every line.
+    pub fn generate_with_deadline(mut self, deadline: Deadline) -> Result<String, Timeout> {
         open_table(&mut self.html);
 
-        for (i, line) in LinesWithEndings::from(self.code).enumerate() {
+        let (start, mut checkpoint_keys) =
+            match self.checkpoint_keys.restore(self.code, self.lines.start) {
+                Some(resume) => {
+                    self.parse_state = resume.checkpoint.parse_state.clone();
+                    self.stack = resume.checkpoint.stack.clone();
+                    (resume.line, resume.keys)
+                }
+                None => (0, self.checkpoint_keys.clone()),
+            };
+
+        for (i, line) in LinesWithEndings::from(self.code)
+            .enumerate()
+            .take(self.lines.end)
+            .skip(start)
+        {
+            deadline.check()?;
+            if let Some(key) = checkpoint_keys.key() {
+                checkpoints::save(key, &self.parse_state, &self.stack);
+            }
+            checkpoint_keys.push_line(line);
+
+            let too_long = self.max_line_len.map_or(false, |n| line.len() > n);
+            if i < self.lines.start {
+                if !too_long {
+                    self.parse_line(line);
+                }
+                continue;
+            }
+
             open_row(&mut self.html, i);
-            if self.max_line_len.map_or(false, |n| line.len() > n) {
+            if too_long {
                 self.write_escaped_html(line);
             } else {
                 self.write_spans_for_line(line);
@@ -69,7 +123,7 @@ impl<'a> ClassedTableGenerator<'a> {
         }
 
         close_table(&mut self.html);
-        self.html
+        Ok(self.html)
     }
 
     // open_current_scopes opens a span for every scope that was still
@@ -110,6 +164,15 @@ impl<'a> ClassedTableGenerator<'a> {
         self.close_current_scopes();
     }
 
+    // parse_line updates the parser state and stack for a line that isn't
+    // part of the output.
+    fn parse_line(&mut self, line: &str) {
+        let parsed_line = self.parse_state.parse_line(line, self.syntax_set);
+        for (_, op) in parsed_line {
+            self.stack.apply(&op);
+        }
+    }
+
     // write_spans_for_tokens creates spans for the list of tokens passed to it.
     // It modifies the stack of the ClassedTableGenerator, adding any scopes
     // that are unclosed at the end of the line.
@@ -170,15 +233,15 @@ impl<'a> ClassedTableGenerator<'a> {
     }
 }
 
-fn open_table(s: &mut String) {
+pub(crate) fn open_table(s: &mut String) {
     s.push_str("<table><tbody>");
 }
 
-fn close_table(s: &mut String) {
+pub(crate) fn close_table(s: &mut String) {
     s.push_str("</tbody></table>");
 }
 
-fn open_row(s: &mut String, i: usize) {
+pub(crate) fn open_row(s: &mut String, i: usize) {
     write!(
         s,
         "<tr><td class=\"line\" data-line=\"{}\"/><td class=\"code\"><div>",
@@ -187,7 +250,7 @@ fn open_row(s: &mut String, i: usize) {
     .unwrap();
 }
 
-fn close_row(s: &mut String) {
+pub(crate) fn close_row(s: &mut String) {
     s.push_str("</div></td></tr>");
 }
 
@@ -234,12 +297,16 @@ impl<'a> fmt::Display for Escape<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{syntect_highlight, SourcegraphQuery};
     use rocket::serde::json::json;
 
+    use crate::{syntect_highlight, SourcegraphQuery, SyntaxEngine};
+
     fn test_css_table_highlight(q: SourcegraphQuery, expected: &str) {
         let result = syntect_highlight(q);
-        assert_eq!(json!({"data": expected, "plaintext": false}), result);
+        assert_eq!(
+            json!({"data": expected, "plaintext": false}),
+            result.unwrap()
+        );
     }
 
     #[test]
@@ -251,7 +318,12 @@ mod tests {
             line_length_limit: None,
             extension: String::new(),
             theme: String::new(),
+            custom_theme: None,
+            engine: SyntaxEngine::Syntect,
+            start_line: None,
+            end_line: None,
             css: true,
+            timeout_ms: None,
         };
         let expected = "<table>\
                             <tbody>\
@@ -271,6 +343,25 @@ mod tests {
         test_css_table_highlight(query, expected)
     }
 
+    #[test]
+    fn line_window() {
+        let query = SourcegraphQuery {
+            filepath: "test.go".to_string(),
+            code: "package main\n/*\ncomment\n*/\n".to_string(),
+            css: true,
+            start_line: Some(2),
+            end_line: Some(3),
+            ..Default::default()
+        };
+        let result = syntect_highlight(query).unwrap();
+        let data = result["data"].as_str().unwrap();
+
+        assert_eq!(data.matches("<tr>").count(), 1);
+        assert!(data.contains("data-line=\"3\""));
+        // The parser is still inside of the comment that started on line 2.
+        assert!(data.contains("<span class=\"hl-comment hl-block hl-go\">comment\n</span>"));
+    }
+
     // See https://github.com/sourcegraph/sourcegraph/issues/20537
     #[test]
     fn long_line_gets_escaped() {
@@ -281,7 +372,12 @@ mod tests {
             line_length_limit: Some(10),
             extension: String::new(),
             theme: String::new(),
+            custom_theme: None,
+            engine: SyntaxEngine::Syntect,
+            start_line: None,
+            end_line: None,
             css: true,
+            timeout_ms: None,
         };
         let expected = "<table>\
                             <tbody>\
@@ -305,7 +401,12 @@ mod tests {
             line_length_limit: Some(5),
             extension: String::new(),
             theme: String::new(),
+            custom_theme: None,
+            engine: SyntaxEngine::Syntect,
+            start_line: None,
+            end_line: None,
             css: true,
+            timeout_ms: None,
         };
         let expected = "<table>\
                             <tbody>\
@@ -330,7 +431,12 @@ mod tests {
             line_length_limit: None,
             extension: String::new(),
             theme: String::new(),
+            custom_theme: None,
+            engine: SyntaxEngine::Syntect,
+            start_line: None,
+            end_line: None,
             css: true,
+            timeout_ms: None,
         };
         let expected = "<table>\
                             <tbody>\
@@ -412,7 +518,12 @@ mod tests {
             line_length_limit: None,
             extension: String::new(),
             theme: String::new(),
+            custom_theme: None,
+            engine: SyntaxEngine::Syntect,
+            start_line: None,
+            end_line: None,
             css: true,
+            timeout_ms: None,
         };
 
         let expected = "<table><tbody><tr><td class=\"line\" data-line=\"1\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\"><span class=\"hl-keyword hl-other hl-matlab\">function</span><span class=\"hl-meta hl-function hl-parameters hl-matlab\"> <span class=\"hl-entity hl-name hl-function hl-matlab\">setupPythonIfNeeded</span><span class=\"hl-punctuation hl-section hl-parens hl-begin hl-matlab\">(</span><span class=\"hl-punctuation hl-section hl-parens hl-end hl-matlab\">)</span></span>\n</span></div></td></tr><tr><td class=\"line\" data-line=\"2\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">\n</span></div></td></tr><tr><td class=\"line\" data-line=\"3\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">            <span class=\"hl-comment hl-line hl-percentage hl-matlab\"><span class=\"hl-punctuation hl-definition hl-comment hl-matlab\">%</span> Python setup is only supported in R2019a (ver 9.6) and later\n</span></span></div></td></tr><tr><td class=\"line\" data-line=\"4\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">\n</span></div></td></tr><tr><td class=\"line\" data-line=\"5\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">            <span class=\"hl-keyword hl-control hl-matlab\">if</span> <span class=\"hl-keyword hl-desktop hl-matlab\">verLessThan</span><span class=\"hl-meta hl-parens hl-matlab\"><span class=\"hl-punctuation hl-section hl-parens hl-begin hl-matlab\">(</span><span class=\"hl-string hl-quoted hl-single hl-matlab\"><span class=\"hl-punctuation hl-definition hl-string hl-begin hl-matlab\">&#39;</span>matlab<span class=\"hl-punctuation hl-definition hl-string hl-end hl-matlab\">&#39;</span></span>,<span class=\"hl-string hl-quoted hl-single hl-matlab\"><span class=\"hl-punctuation hl-definition hl-string hl-begin hl-matlab\">&#39;</span>9.6<span class=\"hl-punctuation hl-definition hl-string hl-end hl-matlab\">&#39;</span></span><span class=\"hl-punctuation hl-section hl-parens hl-end hl-matlab\">)</span></span>\n</span></div></td></tr><tr><td class=\"line\" data-line=\"6\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">\n</span></div></td></tr><tr><td class=\"line\" data-line=\"7\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">            <span class=\"hl-keyword hl-other hl-matlab\">error</span><span class=\"hl-meta hl-parens hl-matlab\"><span class=\"hl-punctuation hl-section hl-parens hl-begin hl-matlab\">(</span><span class=\"hl-string hl-quoted hl-double hl-matlab\"><span class=\"hl-punctuation hl-definition hl-string hl-begin hl-matlab\">&quot;</span>setupPythonIfNeeded:unsupportedVersion<span class=\"hl-punctuation hl-definition hl-string hl-end hl-matlab\">&quot;</span></span>,<span class=\"hl-string hl-quoted hl-double hl-matlab\"><span class=\"hl-punctuation hl-definition hl-string hl-begin hl-matlab\">&quot;</span>Only version R2019a and later are supported<span class=\"hl-punctuation hl-definition hl-string hl-end hl-matlab\">&quot;</span></span><span class=\"hl-punctuation hl-section hl-parens hl-end hl-matlab\">)</span></span>\n</span></div></td></tr><tr><td class=\"line\" data-line=\"8\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">\n</span></div></td></tr><tr><td class=\"line\" data-line=\"9\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">            <span class=\"hl-keyword hl-control hl-matlab\">end</span>\n</span></div></td></tr><tr><td class=\"line\" data-line=\"10\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">\n</span></div></td></tr><tr><td class=\"line\" data-line=\"11\"/><td class=\"code\"><div><span class=\"hl-source hl-matlab\">            <span class=\"hl-keyword hl-control hl-matlab\">end</span></span></div></td></tr></tbody></table>";
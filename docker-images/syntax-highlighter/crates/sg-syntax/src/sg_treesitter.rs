@@ -1,54 +1,63 @@
+use std::sync::atomic::AtomicUsize;
+
 use anyhow::Result;
 use protobuf::Message;
 use rocket::serde::json::{serde_json::json, Value as JsonValue};
 use scip::types::{Document, Occurrence, SyntaxKind};
 use scip_treesitter::types::PackedRange;
-use scip_treesitter_languages::highlights::{
-    get_highlighting_configuration, get_syntax_kind_for_hl,
+use scip_treesitter_languages::{
+    highlights::{get_highlighting_configuration, get_syntax_kind_for_hl},
+    registry,
 };
 use tree_sitter_highlight::{
     Error, Highlight, HighlightConfiguration, HighlightEvent, Highlighter as TSHighlighter,
 };
 
-use crate::SourcegraphQuery;
+use crate::{deadline::Deadline, HighlightError, SourcegraphQuery};
 
 // Handle special cases where syntect language names don't match treesitter names.
 pub fn treesitter_language(syntect_language: &str) -> &str {
-    match syntect_language {
-        "c++" => "cpp",
-        _ => syntect_language,
+    match registry::by_name(syntect_language).and_then(|language| language.parser.as_ref()) {
+        Some(parser) => parser.get_language_name(),
+        None => syntect_language,
     }
 }
 
-pub fn jsonify_err(e: impl ToString) -> JsonValue {
-    json!({"error": e.to_string()})
-}
-
 // TODO(cleanup_lsif): Remove this when we remove /lsif endpoint
 // Currently left unchanged
-pub fn lsif_highlight(q: SourcegraphQuery) -> Result<JsonValue, JsonValue> {
+pub fn lsif_highlight(q: SourcegraphQuery) -> Result<JsonValue, HighlightError> {
     let filetype = q
         .filetype
-        .ok_or_else(|| json!({"error": "Must pass a filetype for /lsif" }))?
+        .ok_or_else(|| {
+            HighlightError::InvalidRequest("Must pass a filetype for /lsif".to_string())
+        })?
         .to_lowercase();
 
-    match index_language(&filetype, &q.code, false) {
-        Ok(document) => {
-            let encoded = document.write_to_bytes().map_err(jsonify_err)?;
+    let deadline = Deadline::from_millis(q.timeout_ms);
+    let document = index_language_with_deadline(&filetype, &q.code, false, deadline)
+        .map_err(|err| HighlightError::from_treesitter(err, &filetype))?;
+    let encoded = document
+        .write_to_bytes()
+        .map_err(|err| HighlightError::Internal(err.to_string()))?;
 
-            Ok(json!({"data": base64::encode(encoded), "plaintext": false}))
-        }
-        Err(Error::InvalidLanguage) => Err(json!({
-            "error": format!("{} is not a valid filetype for treesitter", filetype)
-        })),
-        Err(err) => Err(jsonify_err(err)),
-    }
+    Ok(json!({"data": base64::encode(encoded), "plaintext": false}))
 }
 
 pub fn index_language(filetype: &str, code: &str, include_locals: bool) -> Result<Document, Error> {
+    index_language_with_deadline(filetype, code, include_locals, Deadline::never())
+}
+
+/// Like [`index_language`], but returns [`Error::Cancelled`] once `deadline`
+/// has passed.
+pub fn index_language_with_deadline(
+    filetype: &str,
+    code: &str,
+    include_locals: bool,
+    deadline: Deadline,
+) -> Result<Document, Error> {
     match get_highlighting_configuration(filetype) {
         Some(lang_config) => {
-            index_language_with_config(filetype, code, lang_config, include_locals)
+            index_language_with_config(filetype, code, lang_config, include_locals, deadline)
         }
         None => Err(Error::InvalidLanguage),
     }
@@ -59,75 +68,110 @@ pub fn index_language_with_config(
     code: &str,
     lang_config: &HighlightConfiguration,
     include_locals: bool,
+    deadline: Deadline,
 ) -> Result<Document, Error> {
     // Normalize string to be always only \n endings.
     //  We don't care that the byte offsets are "incorrect" now for this
     //  because we are using a line,col based approach
     let code = code.replace("\r\n", "\n");
 
-    // TODO: We should automatically apply no highlights when we are
-    // in an injected piece of code.
-    //
-    // Unfortunately, that information isn't currently available when
-    // we are iterating in the higlighter.
+    let cancellation_flag = deadline.cancellation_flag();
     let mut highlighter = TSHighlighter::new();
-    let highlights = highlighter.highlight(lang_config, code.as_bytes(), None, |l| {
-        get_highlighting_configuration(l)
-    })?;
-
-    let mut emitter = ScipEmitter::new();
-    let mut doc = emitter.render(highlights, &code, &get_syntax_kind_for_hl)?;
-    doc.occurrences.sort_by_key(|a| (a.range[0], a.range[1]));
+    let mut doc = highlight_document(
+        &mut highlighter,
+        lang_config,
+        &code,
+        Some(cancellation_flag.as_atomic()),
+    )?;
 
     if include_locals {
         let parser = scip_treesitter_languages::parsers::BundledParser::get_parser(filetype);
         if let Some(parser) = parser {
+            // The locals pass parses the code again and can't be cancelled
+            // halfway, so the deadline is checked on both sides of it.
+            if deadline.is_expired() {
+                return Err(Error::Cancelled);
+            }
+
             // TODO: Could probably write this in a much better way.
-            let mut local_occs = scip_syntax::get_locals(parser, code.as_bytes())
+            let local_occs = scip_syntax::get_locals(parser, code.as_bytes())
                 .unwrap_or(Ok(vec![]))
                 .unwrap_or(vec![]);
 
-            // Get ranges in reverse order, because we're going to pop off the back of the list.
-            //  (that's why we're sorting the opposite way of the document occurrences above).
-            local_occs.sort_by_key(|a| (-a.range[0], -a.range[1]));
-
-            let mut next_doc_idx = 0;
-            while let Some(local) = local_occs.pop() {
-                // We *should* be able to assume that all these ranges are valid ranges
-                // but for now we'll skip if they aren't.
-                //
-                // We can add some observability stuff to this later, and/or make
-                // certain builds fail or something to test this out better (but
-                // not have syntax highlighting completely fall apart from one
-                // bad range)
-                let local_range = match PackedRange::from_vec(&local.range) {
-                    Some(range) => range,
-                    None => continue,
-                };
-
-                let (matching_idx, matching_occ) = match doc
-                    .occurrences
-                    .iter_mut()
-                    .enumerate()
-                    .skip(next_doc_idx)
-                    .find(|(_, occ)| local_range.eq_vec(&occ.range))
-                {
-                    Some(found) => found,
-                    None => continue,
-                };
-
-                next_doc_idx = matching_idx;
-
-                // Update occurrence with new information from locals
-                matching_occ.symbol = local.symbol;
-                matching_occ.symbol_roles = local.symbol_roles;
+            if deadline.is_expired() {
+                return Err(Error::Cancelled);
             }
+            merge_locals(&mut doc, local_occs);
         }
     }
 
     Ok(doc)
 }
 
+/// Highlights `code`, which must only have `\n` line endings, and sorts the
+/// occurrences by where they start.
+pub(crate) fn highlight_document(
+    highlighter: &mut TSHighlighter,
+    lang_config: &HighlightConfiguration,
+    code: &str,
+    cancellation_flag: Option<&AtomicUsize>,
+) -> Result<Document, Error> {
+    // TODO: We should automatically apply no highlights when we are
+    // in an injected piece of code.
+    //
+    // Unfortunately, that information isn't currently available when
+    // we are iterating in the higlighter.
+    let highlights =
+        highlighter.highlight(lang_config, code.as_bytes(), cancellation_flag, |l| {
+            get_highlighting_configuration(l)
+        })?;
+
+    let mut emitter = ScipEmitter::new();
+    let mut doc = emitter.render(highlights, code, &get_syntax_kind_for_hl)?;
+    doc.occurrences.sort_by_key(|a| (a.range[0], a.range[1]));
+    Ok(doc)
+}
+
+/// Adds the symbols of `local_occs` to the occurrences of `doc` with the same
+/// range. `doc` has to be sorted, see [`highlight_document`].
+pub(crate) fn merge_locals(doc: &mut Document, mut local_occs: Vec<Occurrence>) {
+    // Get ranges in reverse order, because we're going to pop off the back of the list.
+    //  (that's why we're sorting the opposite way of the document occurrences above).
+    local_occs.sort_by_key(|a| (-a.range[0], -a.range[1]));
+
+    let mut next_doc_idx = 0;
+    while let Some(local) = local_occs.pop() {
+        // We *should* be able to assume that all these ranges are valid ranges
+        // but for now we'll skip if they aren't.
+        //
+        // We can add some observability stuff to this later, and/or make
+        // certain builds fail or something to test this out better (but
+        // not have syntax highlighting completely fall apart from one
+        // bad range)
+        let local_range = match PackedRange::from_vec(&local.range) {
+            Some(range) => range,
+            None => continue,
+        };
+
+        let (matching_idx, matching_occ) = match doc
+            .occurrences
+            .iter_mut()
+            .enumerate()
+            .skip(next_doc_idx)
+            .find(|(_, occ)| local_range.eq_vec(&occ.range))
+        {
+            Some(found) => found,
+            None => continue,
+        };
+
+        next_doc_idx = matching_idx;
+
+        // Update occurrence with new information from locals
+        matching_occ.symbol = local.symbol;
+        matching_occ.symbol_roles = local.symbol_roles;
+    }
+}
+
 struct OffsetManager {
     source: String,
     offsets: Vec<usize>,
@@ -264,7 +308,7 @@ mod test {
     use scip_treesitter::snapshot::dump_document_with_config;
 
     use super::*;
-    use crate::determine_filetype;
+    use crate::{determine_filetype, SyntaxEngine};
 
     fn snapshot_treesitter_syntax_kinds(doc: &Document, source: &str) -> String {
         dump_document_with_config(
@@ -301,6 +345,16 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_locals_respect_the_deadline() {
+        let src = "package main\n\nfunc main() {\n\tx := 1\n\tprintln(x)\n}\n";
+        let deadline = Deadline::after(std::time::Duration::ZERO);
+        assert!(matches!(
+            index_language_with_deadline("go", src, true, deadline),
+            Err(Error::Cancelled)
+        ));
+    }
+
     #[test]
     fn test_highlights_a_sql_query_within_go() -> Result<(), Error> {
         let src = r#"package main
@@ -346,7 +400,12 @@ SELECT * FROM my_table
                 css: false,
                 line_length_limit: None,
                 theme: "".to_string(),
+                custom_theme: None,
+                engine: SyntaxEngine::Syntect,
+                start_line: None,
+                end_line: None,
                 code: contents.clone(),
+                timeout_ms: None,
             });
 
             let indexed = index_language(filetype, &contents, true);
@@ -403,7 +462,12 @@ SELECT * FROM my_table
                 css: false,
                 line_length_limit: None,
                 theme: "".to_string(),
+                custom_theme: None,
+                engine: SyntaxEngine::Syntect,
+                start_line: None,
+                end_line: None,
                 code: contents.clone(),
+                timeout_ms: None,
             });
 
             let indexed = index_language(filetype, &contents, true);
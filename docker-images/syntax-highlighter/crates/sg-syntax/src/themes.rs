@@ -0,0 +1,446 @@
+//! Custom themes for the syntect HTML output.
+//!
+//! Instead of one of the embedded themes, a query can carry the contents of a
+//! theme: either a TextMate/Sublime Text `.tmTheme` file or a VS Code color
+//! theme. Clients tend to send the same theme over and over again, so parsed
+//! themes are kept around, keyed by a hash of their contents.
+
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use lru::LruCache;
+use rocket::serde::json::{json, Value as JsonValue};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use syntect::highlighting::{
+    Color, FontStyle, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSet, ThemeSettings,
+};
+
+use crate::HighlightError;
+
+// Parsed themes are small, so this is mostly about not letting clients that
+// send a different theme every time grow the cache forever.
+const CUSTOM_THEME_CACHE_SIZE: usize = 64;
+
+lazy_static::lazy_static! {
+    static ref CUSTOM_THEMES: Mutex<LruCache<[u8; 32], Arc<Theme>>> = Mutex::new(LruCache::new(
+        NonZeroUsize::new(CUSTOM_THEME_CACHE_SIZE).unwrap()
+    ));
+}
+
+/// Parses the contents of a `.tmTheme` or VS Code theme, or returns the
+/// cached result if we've seen the same theme before.
+pub(crate) fn custom_theme(contents: &str) -> Result<Arc<Theme>, HighlightError> {
+    let key: [u8; 32] = Sha256::digest(contents.as_bytes()).into();
+    if let Some(theme) = CUSTOM_THEMES.lock().unwrap().get(&key) {
+        return Ok(theme.clone());
+    }
+
+    // Invalid themes aren't cached, they should be rare and are cheap to
+    // reject again.
+    let theme = Arc::new(parse_theme(contents)?);
+    CUSTOM_THEMES.lock().unwrap().put(key, theme.clone());
+    Ok(theme)
+}
+
+fn parse_theme(contents: &str) -> Result<Theme, HighlightError> {
+    let json = strip_jsonc(contents);
+    let theme = match contents.trim_start().chars().next() {
+        Some('<') => ThemeSet::load_from_reader(&mut Cursor::new(contents.as_bytes()))
+            .map_err(|err| invalid(format!("invalid .tmTheme: {}", err)))?,
+        _ if json.trim_start().starts_with('{') => parse_vscode_theme(&json)?,
+        _ => {
+            return Err(invalid(
+                "expected the contents of a .tmTheme (XML) or VS Code (JSON) theme".to_string(),
+            ))
+        }
+    };
+
+    if theme.scopes.is_empty()
+        && theme.settings.foreground.is_none()
+        && theme.settings.background.is_none()
+    {
+        return Err(invalid("theme does not define any colors".to_string()));
+    }
+
+    Ok(theme)
+}
+
+/// The colors renderers need to draw the area around the highlighted code so
+/// that it matches the theme, e.g. the background of the line numbers.
+pub(crate) fn theme_metadata(theme: &Theme) -> JsonValue {
+    let settings = &theme.settings;
+    json!({
+        "name": theme.name,
+        "kind": theme_kind(theme),
+        "background": settings.background.map(hex_color),
+        "foreground": settings.foreground.map(hex_color),
+        "selection": settings.selection.map(hex_color),
+        "line_highlight": settings.line_highlight.map(hex_color),
+        "gutter": settings.gutter.map(hex_color),
+        "gutter_foreground": settings.gutter_foreground.map(hex_color),
+    })
+}
+
+/// Whether the theme is a light or a dark one, going by the brightness of
+/// its background. Themes without a background are rendered on white.
+fn theme_kind(theme: &Theme) -> &'static str {
+    match theme.settings.background {
+        Some(Color { r, g, b, .. }) => {
+            // Perceived brightness, see https://www.w3.org/TR/AERT/#color-contrast
+            let brightness = 0.299 * f64::from(r) + 0.587 * f64::from(g) + 0.114 * f64::from(b);
+            if brightness < 128.0 {
+                "dark"
+            } else {
+                "light"
+            }
+        }
+        None => "light",
+    }
+}
+
+fn hex_color(color: Color) -> String {
+    let Color { r, g, b, a } = color;
+    if a == 0xff {
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+fn invalid(message: String) -> HighlightError {
+    HighlightError::InvalidCustomTheme(message)
+}
+
+/// The parts of a VS Code color theme that apply to highlighted code, see
+/// https://code.visualstudio.com/api/extension-guides/color-theme.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VsCodeTheme {
+    name: Option<String>,
+    #[serde(default)]
+    colors: HashMap<String, String>,
+    #[serde(default)]
+    token_colors: Vec<VsCodeTokenColor>,
+}
+
+#[derive(Deserialize)]
+struct VsCodeTokenColor {
+    scope: Option<VsCodeScope>,
+    settings: VsCodeTokenSettings,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VsCodeScope {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VsCodeTokenSettings {
+    foreground: Option<String>,
+    background: Option<String>,
+    font_style: Option<String>,
+}
+
+fn parse_vscode_theme(contents: &str) -> Result<Theme, HighlightError> {
+    let vscode: VsCodeTheme = serde_json::from_str(contents)
+        .map_err(|err| invalid(format!("invalid VS Code theme: {}", err)))?;
+
+    let color = |name: &str| parse_color(vscode.colors.get(name));
+    let mut settings = ThemeSettings {
+        foreground: color("editor.foreground")?,
+        background: color("editor.background")?,
+        selection: color("editor.selectionBackground")?,
+        line_highlight: color("editor.lineHighlightBackground")?,
+        gutter: color("editorGutter.background")?,
+        gutter_foreground: color("editorLineNumber.foreground")?,
+        ..Default::default()
+    };
+
+    let mut scopes = vec![];
+    for token_color in vscode.token_colors {
+        let style = StyleModifier {
+            foreground: parse_color(token_color.settings.foreground.as_ref())?,
+            background: parse_color(token_color.settings.background.as_ref())?,
+            font_style: parse_font_style(token_color.settings.font_style.as_deref())?,
+        };
+
+        let scope = match token_color.scope {
+            Some(VsCodeScope::One(scope)) => scope,
+            Some(VsCodeScope::Many(scopes)) => scopes.join(", "),
+            // Like in .tmTheme files, an entry without a scope sets the
+            // defaults for the whole theme.
+            None => {
+                settings.foreground = settings.foreground.or(style.foreground);
+                settings.background = settings.background.or(style.background);
+                continue;
+            }
+        };
+
+        scopes.push(ThemeItem {
+            scope: ScopeSelectors::from_str(&scope)
+                .map_err(|err| invalid(format!("invalid scope {:?}: {:?}", scope, err)))?,
+            style,
+        });
+    }
+
+    Ok(Theme {
+        name: vscode.name,
+        settings,
+        scopes,
+        ..Default::default()
+    })
+}
+
+/// Turns the JSON with comments and trailing commas that VS Code reads its
+/// themes from into plain JSON. Comments become spaces so that the lines and
+/// columns in parse errors still point at the original contents.
+fn strip_jsonc(contents: &str) -> String {
+    let mut output = String::with_capacity(contents.len());
+    let mut chars = contents.chars().peekable();
+    // Where the last comma went in `output`, as long as only whitespace and
+    // comments followed it.
+    let mut trailing_comma = None;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                output.push(c);
+                while let Some(c) = chars.next() {
+                    output.push(c);
+                    match c {
+                        '\\' => output.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                output.push(' ');
+                while let Some(c) = chars.next_if(|&c| c != '\n') {
+                    output.push(if c == '\r' { c } else { ' ' });
+                }
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                output.push_str("  ");
+                let mut last = None;
+                for c in chars.by_ref() {
+                    output.push(if c.is_whitespace() { c } else { ' ' });
+                    if last == Some('*') && c == '/' {
+                        break;
+                    }
+                    last = Some(c);
+                }
+                continue;
+            }
+            ',' => {
+                trailing_comma = Some(output.len());
+                output.push(c);
+                continue;
+            }
+            '}' | ']' => {
+                if let Some(comma) = trailing_comma {
+                    output.replace_range(comma..comma + 1, " ");
+                }
+                output.push(c);
+            }
+            _ => output.push(c),
+        }
+
+        if !c.is_whitespace() {
+            trailing_comma = None;
+        }
+    }
+
+    output
+}
+
+fn parse_color(color: Option<&String>) -> Result<Option<Color>, HighlightError> {
+    color
+        .map(|color| {
+            Color::from_str(color).map_err(|_| invalid(format!("invalid color {:?}", color)))
+        })
+        .transpose()
+}
+
+fn parse_font_style(font_style: Option<&str>) -> Result<Option<FontStyle>, HighlightError> {
+    font_style
+        .map(|font_style| {
+            // syntect has no way to render strikethrough, so leave it out
+            // rather than rejecting the whole theme.
+            let supported = font_style
+                .split_whitespace()
+                .filter(|style| *style != "strikethrough")
+                .collect::<Vec<_>>()
+                .join(" ");
+            FontStyle::from_str(&supported)
+                .map_err(|_| invalid(format!("invalid font style {:?}", font_style)))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TM_THEME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>name</key>
+  <string>Test</string>
+  <key>settings</key>
+  <array>
+    <dict>
+      <key>settings</key>
+      <dict>
+        <key>foreground</key>
+        <string>#112233</string>
+        <key>background</key>
+        <string>#000000</string>
+      </dict>
+    </dict>
+    <dict>
+      <key>scope</key>
+      <string>keyword</string>
+      <key>settings</key>
+      <dict>
+        <key>foreground</key>
+        <string>#FF0000</string>
+      </dict>
+    </dict>
+  </array>
+</dict>
+</plist>"#;
+
+    const VSCODE_THEME: &str = r##"{
+        "name": "Test",
+        "colors": {"editor.foreground": "#112233", "editor.background": "#000000"},
+        "tokenColors": [
+            {"scope": ["keyword", "storage.type"], "settings": {"foreground": "#ff0000", "fontStyle": "bold strikethrough"}},
+            {"scope": "comment", "settings": {"foreground": "#00ff00"}}
+        ]
+    }"##;
+
+    // VS Code reads themes as JSON with comments, and plenty of them have
+    // trailing commas too.
+    const VSCODE_JSONC_THEME: &str = r##"// Exported from VS Code
+    {
+        "name": "Test // not a comment",
+        /* "name": "Commented out", */
+        "colors": {
+            "editor.foreground": "#112233", // the default text color
+            "editor.background": "#000000",
+        },
+        "tokenColors": [
+            {"scope": "string", "settings": {"foreground": "#a0a0a0",},},
+        ],
+    }"##;
+
+    #[test]
+    fn parses_tm_theme() {
+        let theme = parse_theme(TM_THEME).unwrap();
+        assert_eq!(theme.name.as_deref(), Some("Test"));
+        assert_eq!(theme.scopes.len(), 1);
+    }
+
+    #[test]
+    fn parses_vscode_theme() {
+        let theme = parse_theme(VSCODE_THEME).unwrap();
+        assert_eq!(theme.name.as_deref(), Some("Test"));
+        assert_eq!(
+            theme.settings.foreground,
+            Some(Color {
+                r: 0x11,
+                g: 0x22,
+                b: 0x33,
+                a: 0xff
+            })
+        );
+        assert_eq!(theme.scopes.len(), 2);
+        assert_eq!(theme.scopes[0].style.font_style, Some(FontStyle::BOLD));
+    }
+
+    #[test]
+    fn parses_vscode_theme_with_comments_and_trailing_commas() {
+        let theme = parse_theme(VSCODE_JSONC_THEME).unwrap();
+        assert_eq!(theme.name.as_deref(), Some("Test // not a comment"));
+        assert_eq!(
+            theme.settings.background,
+            Some(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0xff
+            })
+        );
+        assert_eq!(theme.scopes.len(), 1);
+    }
+
+    #[test]
+    fn strips_jsonc() {
+        assert_eq!(
+            strip_jsonc("{\"a\": [1, 2,], /* b */ \"c\": \"\\\"//\",} // end"),
+            "{\"a\": [1, 2 ],         \"c\": \"\\\"//\" }       "
+        );
+    }
+
+    #[test]
+    fn metadata() {
+        let theme = parse_theme(VSCODE_THEME).unwrap();
+        assert_eq!(
+            theme_metadata(&theme),
+            json!({
+                "name": "Test",
+                "kind": "dark",
+                "background": "#000000",
+                "foreground": "#112233",
+                "selection": null,
+                "line_highlight": null,
+                "gutter": null,
+                "gutter_foreground": null,
+            })
+        );
+
+        let mut theme = parse_theme(TM_THEME).unwrap();
+        theme.settings.background = Some(Color {
+            r: 0xfd,
+            g: 0xf6,
+            b: 0xe3,
+            a: 0x80,
+        });
+        let metadata = theme_metadata(&theme);
+        assert_eq!(metadata["kind"], "light");
+        assert_eq!(metadata["background"], "#fdf6e380");
+    }
+
+    #[test]
+    fn rejects_invalid_themes() {
+        for contents in [
+            "Solarized (dark)",
+            "<plist",
+            r#"{"tokenColors": [{"scope": "keyword", "settings": {"foreground": "red"}}]}"#,
+            r#"{"name": "no colors"}"#,
+        ] {
+            let err = parse_theme(contents).unwrap_err();
+            assert_eq!(err.code(), "invalid_custom_theme", "{}", contents);
+        }
+    }
+
+    #[test]
+    fn caches_themes() {
+        let first = custom_theme(VSCODE_THEME).unwrap();
+        let second = custom_theme(VSCODE_THEME).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+}
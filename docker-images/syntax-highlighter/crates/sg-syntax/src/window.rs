@@ -0,0 +1,105 @@
+//! Highlighting a window of lines instead of the whole file.
+//!
+//! Lines are zero-based, and the end of a window is exclusive, like the
+//! ranges of SCIP occurrences. Everything before the window still has to be
+//! parsed, so that the window starts in the right state, but only the window
+//! itself ends up in the output.
+
+use std::ops::Range;
+
+use scip::types::Document;
+use scip_treesitter::types::PackedRange;
+
+use crate::HighlightError;
+
+/// The lines to highlight. A window without a start or end goes to the start
+/// or end of the file.
+pub(crate) fn line_window(
+    start_line: Option<usize>,
+    end_line: Option<usize>,
+) -> Result<Range<usize>, HighlightError> {
+    let window = start_line.unwrap_or(0)..end_line.unwrap_or(usize::MAX);
+    if window.start > window.end {
+        return Err(HighlightError::InvalidRequest(format!(
+            "start_line ({}) must not be after end_line ({})",
+            window.start, window.end
+        )));
+    }
+    Ok(window)
+}
+
+/// Drops the occurrences outside of `lines`, and cuts the ones that start
+/// before or end after it at the window's boundaries.
+pub(crate) fn clip_document(document: &mut Document, lines: &Range<usize>) {
+    if lines.start == 0 && lines.end == usize::MAX {
+        return;
+    }
+
+    let start = (i32::try_from(lines.start).unwrap_or(i32::MAX), 0);
+    let end = (i32::try_from(lines.end).unwrap_or(i32::MAX), 0);
+    document.occurrences.retain_mut(|occurrence| {
+        let mut range = match PackedRange::from_vec(&occurrence.range) {
+            Some(range) => range,
+            None => return false,
+        };
+        if (range.end_line, range.end_col) <= start || (range.start_line, range.start_col) >= end {
+            return false;
+        }
+
+        if (range.start_line, range.start_col) < start {
+            (range.start_line, range.start_col) = start;
+        }
+        if (range.end_line, range.end_col) > end {
+            (range.end_line, range.end_col) = end;
+        }
+        occurrence.range = range.to_vec();
+        true
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use scip::types::Occurrence;
+
+    use super::*;
+
+    #[test]
+    fn invalid_window() {
+        assert_eq!(line_window(None, None).unwrap(), 0..usize::MAX);
+        assert_eq!(line_window(Some(3), Some(3)).unwrap(), 3..3);
+        assert_eq!(
+            line_window(Some(4), Some(3)).unwrap_err().code(),
+            "invalid_request"
+        );
+    }
+
+    #[test]
+    fn clips_occurrences() {
+        let occurrence = |range: Vec<i32>| Occurrence {
+            range,
+            ..Default::default()
+        };
+        let mut document = Document {
+            occurrences: vec![
+                occurrence(vec![0, 0, 5]),
+                occurrence(vec![0, 2, 2, 0]),
+                occurrence(vec![1, 0, 3, 4]),
+                occurrence(vec![2, 1, 4]),
+                occurrence(vec![3, 0, 4, 1]),
+                occurrence(vec![4, 0, 1]),
+            ],
+            ..Default::default()
+        };
+
+        clip_document(&mut document, &(2..4));
+        let ranges = document
+            .occurrences
+            .iter()
+            .map(|occurrence| occurrence.range.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            vec![vec![2, 0, 3, 4], vec![2, 1, 4], vec![3, 0, 4, 0]]
+        );
+    }
+}
@@ -49,6 +49,11 @@ fn main() -> Result<(), std::io::Error> {
         css: false,
         line_length_limit: None,
         theme: "".to_string(),
+        custom_theme: None,
+        engine: sg_syntax::SyntaxEngine::Syntect,
+        start_line: None,
+        end_line: None,
+        timeout_ms: None,
     });
 
     println!("  filetype: {:?}", filetype);
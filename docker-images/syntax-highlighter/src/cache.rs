@@ -0,0 +1,313 @@
+//! In-memory cache of highlighting results.
+//!
+//! Popular files get highlighted over and over again, so successful results
+//! are kept in an LRU cache keyed by a hash of everything that influences the
+//! output. The cache is bounded by the (approximate) size of the results it
+//! holds, not by the number of entries, since one minified file can easily be
+//! larger than thousands of small ones.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+use lru::LruCache;
+use protobuf::Message;
+use rocket::serde::json::{json, Value as JsonValue};
+use scip::types::Document;
+use sg_syntax::HighlightError;
+use sha2::{Digest, Sha256};
+
+/// Hash of a query, see [`KeyBuilder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key([u8; 32]);
+
+/// Builds a [`Key`] out of every part of a query that affects its result.
+pub struct KeyBuilder(Sha256);
+
+impl KeyBuilder {
+    /// `kind` separates the different kinds of queries, so that e.g. a
+    /// `/symbols` query never returns a cached `/scip` result.
+    pub fn new(kind: &str) -> Self {
+        Self(Sha256::new()).str(kind)
+    }
+
+    pub fn bytes(mut self, part: &[u8]) -> Self {
+        // Length-prefix each part, so that ("ab", "c") and ("a", "bc") don't
+        // end up with the same key.
+        self.0.update((part.len() as u64).to_le_bytes());
+        self.0.update(part);
+        self
+    }
+
+    pub fn str(self, part: &str) -> Self {
+        self.bytes(part.as_bytes())
+    }
+
+    pub fn opt_str(self, part: Option<&str>) -> Self {
+        match part {
+            Some(part) => self.bytes(b"some").str(part),
+            None => self.bytes(b"none"),
+        }
+    }
+
+    pub fn opt_usize(self, part: Option<usize>) -> Self {
+        self.opt_str(part.map(|part| part.to_string()).as_deref())
+    }
+
+    pub fn finish(self) -> Key {
+        Key(self.0.finalize().into())
+    }
+}
+
+#[derive(Clone)]
+enum Entry {
+    Json(JsonValue),
+    Document(Document),
+}
+
+struct Entries {
+    lru: LruCache<Key, (Entry, usize)>,
+    size: usize,
+    capacity: usize,
+}
+
+pub struct Cache {
+    // None if the cache is disabled.
+    entries: Option<Mutex<Entries>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Cache {
+    /// Creates a cache that holds at most `capacity` bytes of results. A
+    /// capacity of zero disables caching.
+    pub fn new(capacity: usize) -> Self {
+        let entries = (capacity > 0).then(|| {
+            Mutex::new(Entries {
+                lru: LruCache::unbounded(),
+                size: 0,
+                capacity,
+            })
+        });
+
+        Self {
+            entries,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached JSON response for `key`, or computes it with `f`.
+    /// Errors are not cached.
+    pub fn json(
+        &self,
+        key: Key,
+        f: impl FnOnce() -> Result<JsonValue, HighlightError>,
+    ) -> Result<JsonValue, HighlightError> {
+        if let Some(Entry::Json(value)) = self.get(&key) {
+            return Ok(value);
+        }
+
+        let value = f()?;
+        let size = value.to_string().len();
+        self.insert(key, Entry::Json(value.clone()), size);
+        Ok(value)
+    }
+
+    /// Returns the cached document for `key`, or computes it with `f`. Errors
+    /// are not cached.
+    pub fn document(
+        &self,
+        key: Key,
+        f: impl FnOnce() -> Result<Document, HighlightError>,
+    ) -> Result<Document, HighlightError> {
+        if let Some(Entry::Document(document)) = self.get(&key) {
+            return Ok(document);
+        }
+
+        let document = f()?;
+        let size = document.compute_size() as usize;
+        self.insert(key, Entry::Document(document.clone()), size);
+        Ok(document)
+    }
+
+    pub fn stats(&self) -> JsonValue {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let hit_rate = match hits + misses {
+            0 => 0.0,
+            total => hits as f64 / total as f64,
+        };
+
+        let (entries, size, capacity) = match &self.entries {
+            Some(entries) => {
+                let entries = entries.lock().unwrap();
+                (entries.lru.len(), entries.size, entries.capacity)
+            }
+            None => (0, 0, 0),
+        };
+
+        json!({
+            "enabled": self.entries.is_some(),
+            "hits": hits,
+            "misses": misses,
+            "hit_rate": hit_rate,
+            "entries": entries,
+            "size_bytes": size,
+            "capacity_bytes": capacity,
+        })
+    }
+
+    fn get(&self, key: &Key) -> Option<Entry> {
+        let entries = self.entries.as_ref()?;
+        let entry = entries
+            .lock()
+            .unwrap()
+            .lru
+            .get(key)
+            .map(|(entry, _)| entry.clone());
+
+        match entry {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        entry
+    }
+
+    fn insert(&self, key: Key, entry: Entry, size: usize) {
+        let entries = match &self.entries {
+            Some(entries) => entries,
+            None => return,
+        };
+
+        let mut entries = entries.lock().unwrap();
+        // Caching this would evict everything else and still not fit.
+        if size > entries.capacity {
+            return;
+        }
+
+        if let Some((_, old_size)) = entries.lru.put(key, (entry, size)) {
+            entries.size -= old_size;
+        }
+        entries.size += size;
+
+        while entries.size > entries.capacity {
+            match entries.lru.pop_lru() {
+                Some((_, (_, evicted_size))) => entries.size -= evicted_size,
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn key(name: &str) -> Key {
+        KeyBuilder::new("test").str(name).finish()
+    }
+
+    /// A JSON string that takes up exactly `size` bytes in the cache.
+    fn value(size: usize) -> JsonValue {
+        json!("a".repeat(size - 2))
+    }
+
+    /// Looks up `name`, and returns whether it had to be computed.
+    fn computed(cache: &Cache, name: &str, size: usize) -> bool {
+        let computed = Cell::new(false);
+        let result = cache.json(key(name), || {
+            computed.set(true);
+            Ok(value(size))
+        });
+        assert_eq!(result.unwrap(), value(size));
+        computed.get()
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries() {
+        let cache = Cache::new(10);
+
+        assert!(computed(&cache, "a", 4));
+        assert!(computed(&cache, "b", 4));
+        assert!(!computed(&cache, "a", 4));
+        // Doesn't fit next to both, so "b", which was used longest ago, goes.
+        assert!(computed(&cache, "c", 4));
+
+        let stats = cache.stats();
+        assert_eq!(stats["entries"], 2);
+        assert_eq!(stats["size_bytes"], 8);
+        assert!(!computed(&cache, "a", 4));
+        assert!(!computed(&cache, "c", 4));
+        assert!(computed(&cache, "b", 4));
+    }
+
+    #[test]
+    fn skips_entries_larger_than_the_cache() {
+        let cache = Cache::new(10);
+
+        assert!(computed(&cache, "small", 4));
+        assert!(computed(&cache, "large", 11));
+        assert!(computed(&cache, "large", 11));
+        assert!(!computed(&cache, "small", 4));
+
+        let stats = cache.stats();
+        assert_eq!(stats["entries"], 1);
+        assert_eq!(stats["size_bytes"], 4);
+    }
+
+    #[test]
+    fn replacing_an_entry_replaces_its_size() {
+        let cache = Cache::new(10);
+
+        cache.insert(key("a"), Entry::Json(value(4)), 4);
+        cache.insert(key("a"), Entry::Json(value(6)), 6);
+
+        let stats = cache.stats();
+        assert_eq!(stats["entries"], 1);
+        assert_eq!(stats["size_bytes"], 6);
+    }
+
+    #[test]
+    fn does_not_cache_errors() {
+        let cache = Cache::new(10);
+
+        let result = cache.json(key("a"), || Err(HighlightError::Timeout));
+        assert!(result.is_err());
+        assert_eq!(cache.stats()["entries"], 0);
+        assert!(computed(&cache, "a", 4));
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = Cache::new(10);
+
+        assert!(computed(&cache, "a", 4));
+        for _ in 0..3 {
+            assert!(!computed(&cache, "a", 4));
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats["enabled"], true);
+        assert_eq!(stats["hits"], 3);
+        assert_eq!(stats["misses"], 1);
+        assert_eq!(stats["hit_rate"], 0.75);
+        assert_eq!(stats["capacity_bytes"], 10);
+    }
+
+    #[test]
+    fn caches_nothing_when_disabled() {
+        let cache = Cache::new(0);
+
+        assert!(computed(&cache, "a", 4));
+        assert!(computed(&cache, "a", 4));
+
+        let stats = cache.stats();
+        assert_eq!(stats["enabled"], false);
+        assert_eq!(stats["entries"], 0);
+        assert_eq!(stats["hit_rate"], 0.0);
+    }
+}
@@ -0,0 +1,467 @@
+//! Server configuration.
+//!
+//! Settings come from, in increasing order of precedence: built-in defaults,
+//! a TOML file passed with `--config`, and command-line flags. Every flag can
+//! also be set with an environment variable, which keeps deployments that
+//! configure the server through `ROCKET_PORT`, `QUIET` and friends working.
+
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use rocket::figment::Figment;
+use scip_treesitter_languages::parsers::BundledParser;
+use serde::{Deserialize, Serialize};
+use sg_syntax::{HighlightError, Limits, SyntaxEngine};
+
+#[derive(Parser, Debug)]
+#[command(about = "Syntax highlighting server for Sourcegraph")]
+struct Args {
+    /// TOML file to read settings from. Flags take precedence over it.
+    #[arg(long, env = "SYNTECT_SERVER_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Print the resulting settings as TOML and exit.
+    #[arg(long)]
+    print_config: bool,
+
+    /// Address to serve HTTP on.
+    #[arg(long, env = "ROCKET_ADDRESS")]
+    address: Option<IpAddr>,
+
+    /// Port to serve HTTP on.
+    #[arg(long, env = "ROCKET_PORT")]
+    port: Option<u16>,
+
+    /// Port to also serve the gRPC API on, on all interfaces.
+    #[arg(long, env = "GRPC_PORT")]
+    grpc_port: Option<u16>,
+
+    /// Number of threads handling HTTP requests.
+    #[arg(long, env = "ROCKET_WORKERS")]
+    workers: Option<usize>,
+
+    /// Comma-separated list of engines to serve requests for.
+    #[arg(long, env = "ENGINES", value_delimiter = ',')]
+    engines: Option<Vec<SyntaxEngine>>,
+
+    /// Comma-separated list of tree-sitter languages to load on startup.
+    #[arg(long, env = "PRELOAD_LANGUAGES", value_delimiter = ',')]
+    preload_languages: Option<Vec<String>>,
+
+    /// Size of the result cache in bytes. Zero disables it.
+    #[arg(long, env = "CACHE_SIZE_BYTES")]
+    cache_size_bytes: Option<usize>,
+
+    /// Timeout for requests that don't set `timeout_ms`. Zero, the default,
+    /// disables it.
+    #[arg(long, env = "HIGHLIGHT_TIMEOUT_MS")]
+    timeout_ms: Option<u64>,
+
+    /// Files larger than this are highlighted more cheaply.
+    #[arg(long, env = "MAX_FILE_BYTES")]
+    max_file_bytes: Option<usize>,
+
+    /// Files with more lines than this are highlighted more cheaply.
+    #[arg(long, env = "MAX_FILE_LINES")]
+    max_file_lines: Option<usize>,
+
+    /// Files with longer lines than this are highlighted more cheaply.
+    #[arg(long, env = "MAX_LINE_LENGTH")]
+    max_line_length: Option<usize>,
+
+    #[arg(long, env = "LOG_FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Don't list the supported themes and languages on startup. `QUIET`
+    /// only turns this on if it is exactly `true`.
+    #[arg(
+        long,
+        env = "QUIET",
+        value_parser = quiet_from_env,
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    quiet: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Rocket's human-readable output.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Everything the server can be configured with, see `--help`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub address: IpAddr,
+    pub port: u16,
+    pub grpc_port: Option<u16>,
+    pub workers: usize,
+    pub engines: Vec<SyntaxEngine>,
+    pub preload_languages: Vec<String>,
+    pub cache_size_bytes: usize,
+    pub timeout_ms: u64,
+    pub log_format: LogFormat,
+    pub quiet: bool,
+    pub limits: Limits,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            // Same as Rocket's defaults.
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8000,
+            grpc_port: None,
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            engines: vec![
+                SyntaxEngine::Syntect,
+                SyntaxEngine::TreeSitter,
+                SyntaxEngine::ScipSyntax,
+            ],
+            preload_languages: vec![],
+            // The cache is off by default, since the memory it needs depends on
+            // how many copies of the server are running side by side.
+            cache_size_bytes: 0,
+            // No timeout by default, so that slow files still finish like
+            // they did before timeouts were configurable.
+            timeout_ms: 0,
+            log_format: LogFormat::Text,
+            quiet: false,
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings from the config file and command line. Exits with a
+    /// usage error if they are invalid, or after printing them if
+    /// `--print-config` was passed.
+    pub fn load() -> Self {
+        let args = Args::parse();
+        let settings = Self::from_args(&args).unwrap_or_else(|(kind, message)| exit(kind, message));
+
+        if args.print_config {
+            print!("{}", settings.to_toml());
+            std::process::exit(0)
+        }
+
+        settings
+    }
+
+    fn from_args(args: &Args) -> Result<Self, (ErrorKind, String)> {
+        let mut settings = match &args.config {
+            Some(path) => Self::read(path).map_err(|err| (ErrorKind::Io, err))?,
+            None => Self::default(),
+        };
+        settings.apply(args);
+        settings
+            .validate()
+            .map_err(|err| (ErrorKind::InvalidValue, err))?;
+        Ok(settings)
+    }
+
+    /// The settings as a config file, which is what `--print-config` prints.
+    fn to_toml(&self) -> String {
+        toml::to_string(self).expect("settings are valid toml")
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        toml::from_str(&contents).map_err(|err| format!("invalid {}: {}", path.display(), err))
+    }
+
+    fn apply(&mut self, args: &Args) {
+        if let Some(address) = args.address {
+            self.address = address;
+        }
+        if let Some(port) = args.port {
+            self.port = port;
+        }
+        if let Some(grpc_port) = args.grpc_port {
+            self.grpc_port = Some(grpc_port);
+        }
+        if let Some(workers) = args.workers {
+            self.workers = workers;
+        }
+        if let Some(engines) = &args.engines {
+            self.engines = engines.clone();
+        }
+        if let Some(preload_languages) = &args.preload_languages {
+            self.preload_languages = preload_languages.clone();
+        }
+        if let Some(cache_size_bytes) = args.cache_size_bytes {
+            self.cache_size_bytes = cache_size_bytes;
+        }
+        if let Some(timeout_ms) = args.timeout_ms {
+            self.timeout_ms = timeout_ms;
+        }
+        if let Some(max_bytes) = args.max_file_bytes {
+            self.limits.max_bytes = Some(max_bytes);
+        }
+        if let Some(max_lines) = args.max_file_lines {
+            self.limits.max_lines = Some(max_lines);
+        }
+        if let Some(max_line_length) = args.max_line_length {
+            self.limits.max_line_length = Some(max_line_length);
+        }
+        if let Some(log_format) = args.log_format {
+            self.log_format = log_format;
+        }
+        if let Some(quiet) = args.quiet {
+            self.quiet = quiet;
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.workers == 0 {
+            return Err("workers must be at least 1".to_string());
+        }
+
+        if self.engines.is_empty() {
+            return Err("at least one engine must be enabled".to_string());
+        }
+
+        if self.grpc_port == Some(self.port) {
+            return Err(format!(
+                "port and grpc_port must be different, both are {}",
+                self.port
+            ));
+        }
+
+        for language in &self.preload_languages {
+            if BundledParser::get_parser(language).is_none() {
+                return Err(format!(
+                    "unknown language in preload_languages: {}",
+                    language
+                ));
+            }
+        }
+
+        let Limits {
+            max_bytes,
+            max_lines,
+            max_line_length,
+        } = self.limits;
+        if [max_bytes, max_lines, max_line_length].contains(&Some(0)) {
+            return Err("limits must be greater than zero".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// The settings that apply to individual requests.
+    pub fn config(&self) -> Config {
+        Config {
+            // A timeout of zero turns the default timeout off.
+            default_timeout_ms: Some(self.timeout_ms).filter(|&ms| ms > 0),
+            limits: self.limits,
+            engines: Engines::new(&self.engines),
+        }
+    }
+
+    /// Rocket's own configuration, with our settings taking precedence over
+    /// the `ROCKET_*` environment variables and `Rocket.toml`.
+    pub fn figment(&self) -> Figment {
+        rocket::Config::figment()
+            .merge(("address", self.address))
+            .merge(("port", self.port))
+            .merge(("workers", self.workers))
+    }
+}
+
+fn exit(kind: ErrorKind, message: String) -> ! {
+    Args::command().error(kind, message).exit()
+}
+
+/// Deployments set `QUIET` to all kinds of values, which only ever counted
+/// when they were exactly `true`, so any of them has to keep starting up.
+fn quiet_from_env(value: &str) -> Result<bool, std::convert::Infallible> {
+    Ok(value == "true")
+}
+
+/// Server-wide settings that apply to every request.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Used for queries that don't set `timeout_ms` themselves.
+    pub default_timeout_ms: Option<u64>,
+
+    /// Files over these limits get a cheaper fallback.
+    pub limits: Limits,
+
+    pub engines: Engines,
+}
+
+impl Config {
+    pub fn timeout_ms(&self, requested: Option<u64>) -> Option<u64> {
+        requested.or(self.default_timeout_ms)
+    }
+
+    /// Rejects requests for engines that were turned off.
+    pub fn check_engine(&self, engine: SyntaxEngine) -> Result<(), HighlightError> {
+        if self.engines.is_enabled(engine) {
+            Ok(())
+        } else {
+            Err(HighlightError::InvalidRequest(format!(
+                "the {} engine is disabled",
+                engine.as_str()
+            )))
+        }
+    }
+}
+
+/// The set of enabled engines. Kept as flags rather than a `Vec` so that
+/// [`Config`] stays `Copy`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Engines {
+    syntect: bool,
+    tree_sitter: bool,
+    scip_syntax: bool,
+}
+
+impl Engines {
+    fn new(engines: &[SyntaxEngine]) -> Self {
+        let mut enabled = Self::default();
+        for engine in engines {
+            match engine {
+                SyntaxEngine::Syntect => enabled.syntect = true,
+                SyntaxEngine::TreeSitter => enabled.tree_sitter = true,
+                SyntaxEngine::ScipSyntax => enabled.scip_syntax = true,
+            }
+        }
+        enabled
+    }
+
+    fn is_enabled(&self, engine: SyntaxEngine) -> bool {
+        match engine {
+            SyntaxEngine::Syntect => self.syntect,
+            SyntaxEngine::TreeSitter => self.tree_sitter,
+            SyntaxEngine::ScipSyntax => self.scip_syntax,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flags_and_environment_override_the_config_file() {
+        let path = std::env::temp_dir().join(format!("syntect-server-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "port = 1111\nworkers = 2\ncache_size_bytes = 100\nquiet = true\n",
+        )
+        .unwrap();
+        // No other test parses arguments, so this doesn't leak into them.
+        std::env::set_var("CACHE_SIZE_BYTES", "200");
+
+        let parse = |flags: &[&str]| {
+            let args = ["syntect_server", "--config", path.to_str().unwrap()]
+                .into_iter()
+                .chain(flags.iter().copied());
+            Settings::from_args(&Args::try_parse_from(args).unwrap())
+        };
+        let from_file_and_env = parse(&[]);
+        let from_flags = parse(&[
+            "--workers",
+            "3",
+            "--cache-size-bytes",
+            "300",
+            "--quiet=false",
+        ]);
+
+        std::env::remove_var("CACHE_SIZE_BYTES");
+        std::fs::remove_file(&path).unwrap();
+
+        let settings = from_file_and_env.unwrap();
+        assert_eq!(settings.port, 1111);
+        assert_eq!(settings.workers, 2);
+        assert_eq!(settings.cache_size_bytes, 200);
+        assert!(settings.quiet);
+
+        let settings = from_flags.unwrap();
+        assert_eq!(settings.port, 1111);
+        assert_eq!(settings.workers, 3);
+        assert_eq!(settings.cache_size_bytes, 300);
+        assert!(!settings.quiet);
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(Settings::default().validate().is_ok());
+
+        for settings in [
+            Settings {
+                workers: 0,
+                ..Default::default()
+            },
+            Settings {
+                engines: vec![],
+                ..Default::default()
+            },
+            Settings {
+                grpc_port: Some(8000),
+                ..Default::default()
+            },
+            Settings {
+                preload_languages: vec!["klingon".to_string()],
+                ..Default::default()
+            },
+            Settings {
+                limits: Limits {
+                    max_lines: Some(0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ] {
+            assert!(settings.validate().is_err(), "{:?} is valid", settings);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(toml::from_str::<Settings>("workers = 2").is_ok());
+        assert!(toml::from_str::<Settings>("colour = true").is_err());
+        assert!(toml::from_str::<Settings>("[limits]\nmax_files = 1").is_err());
+    }
+
+    #[test]
+    fn prints_a_config_file_that_reads_back_the_same() {
+        let settings = Settings {
+            grpc_port: Some(9000),
+            engines: vec![SyntaxEngine::TreeSitter],
+            limits: Limits {
+                max_bytes: Some(1024),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let printed = settings.to_toml();
+        assert!(printed.contains("grpc_port = 9000\n"), "{}", printed);
+        assert!(
+            printed.contains("engines = [\"tree-sitter\"]\n"),
+            "{}",
+            printed
+        );
+        assert!(
+            printed.contains("[limits]\nmax_bytes = 1024\n"),
+            "{}",
+            printed
+        );
+        assert_eq!(
+            toml::from_str::<Settings>(&printed).unwrap().to_toml(),
+            printed
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! HTTP responses for [`HighlightError`].
+
+use rocket::{
+    http::Status,
+    response::{self, Responder},
+    Request,
+};
+use sg_syntax::HighlightError;
+
+/// Responds with the JSON form of the error and a matching HTTP status code.
+#[derive(Debug)]
+pub struct ApiError(pub HighlightError);
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self.0 {
+            HighlightError::InvalidRequest(_)
+            | HighlightError::InvalidTheme(_)
+            | HighlightError::InvalidCustomTheme(_) => Status::BadRequest,
+            HighlightError::UnknownLanguage(_) | HighlightError::ParseFailed(_) => {
+                Status::UnprocessableEntity
+            }
+            HighlightError::TooLarge(_) => Status::PayloadTooLarge,
+            HighlightError::Timeout => Status::ServiceUnavailable,
+            HighlightError::Panic | HighlightError::Internal(_) => Status::InternalServerError,
+        }
+    }
+}
+
+impl From<HighlightError> for ApiError {
+    fn from(err: HighlightError) -> Self {
+        Self(err)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        (self.status(), self.0.to_json()).respond_to(request)
+    }
+}
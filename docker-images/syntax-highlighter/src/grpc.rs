@@ -0,0 +1,423 @@
+//! gRPC service that runs alongside the JSON HTTP API.
+//!
+//! The schema lives in proto/syntaxhighlight/v1/syntaxhighlight.proto. The
+//! messages below are written by hand to match it (see build.rs for why), so
+//! keep the two in sync.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use prost::{
+    bytes::{Buf, BufMut},
+    encoding::{self, DecodeContext, WireType},
+    DecodeError,
+};
+use protobuf::Message as _;
+use scip::types::Document;
+use scip_syntax::ctags::{for_each_tag, Reply};
+use sg_syntax::{Degradation, HighlightError, ScipHighlightQuery};
+use tonic::{Request, Response, Status};
+
+use crate::{cache::Cache, config::Config, metrics::RequestTimer};
+
+include!(concat!(
+    env!("OUT_DIR"),
+    "/syntaxhighlight.v1.SyntaxHighlighter.rs"
+));
+
+use syntax_highlighter_server::{SyntaxHighlighter, SyntaxHighlighterServer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum SyntaxEngine {
+    Unspecified = 0,
+    Syntect = 1,
+    TreeSitter = 2,
+    ScipSyntax = 3,
+}
+
+impl From<SyntaxEngine> for sg_syntax::SyntaxEngine {
+    fn from(engine: SyntaxEngine) -> Self {
+        match engine {
+            SyntaxEngine::Unspecified | SyntaxEngine::Syntect => sg_syntax::SyntaxEngine::Syntect,
+            SyntaxEngine::TreeSitter => sg_syntax::SyntaxEngine::TreeSitter,
+            SyntaxEngine::ScipSyntax => sg_syntax::SyntaxEngine::ScipSyntax,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HighlightRequest {
+    #[prost(enumeration = "SyntaxEngine", tag = "1")]
+    pub engine: i32,
+    #[prost(string, tag = "2")]
+    pub code: String,
+    #[prost(string, tag = "3")]
+    pub filepath: String,
+    #[prost(string, optional, tag = "4")]
+    pub filetype: Option<String>,
+    #[prost(uint32, optional, tag = "5")]
+    pub line_length_limit: Option<u32>,
+    #[prost(uint64, optional, tag = "6")]
+    pub timeout_ms: Option<u64>,
+    #[prost(uint32, optional, tag = "7")]
+    pub start_line: Option<u32>,
+    #[prost(uint32, optional, tag = "8")]
+    pub end_line: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HighlightResponse {
+    #[prost(message, optional, tag = "1")]
+    pub document: Option<ScipDocument>,
+    #[prost(bool, tag = "2")]
+    pub plaintext: bool,
+    #[prost(string, optional, tag = "3")]
+    pub degraded: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SymbolsRequest {
+    #[prost(string, tag = "1")]
+    pub filename: String,
+    #[prost(string, tag = "2")]
+    pub content: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SymbolsResponse {
+    #[prost(message, optional, tag = "1")]
+    pub document: Option<ScipDocument>,
+    #[prost(string, optional, tag = "2")]
+    pub degraded: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CtagsRequest {
+    #[prost(string, tag = "1")]
+    pub filename: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub content: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CtagsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Tag {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub path: String,
+    #[prost(string, tag = "3")]
+    pub language: String,
+    #[prost(uint32, tag = "4")]
+    pub line: u32,
+    #[prost(string, tag = "5")]
+    pub kind: String,
+    #[prost(string, optional, tag = "6")]
+    pub scope: Option<String>,
+}
+
+/// Lets prost messages embed a `scip::types::Document`.
+///
+/// The `scip` crate generates its types with rust-protobuf, while tonic uses
+/// prost. Both produce the same wire format, so this wrapper hands the
+/// encoding and decoding of the document over to rust-protobuf.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScipDocument(pub Document);
+
+impl prost::Message for ScipDocument {
+    fn encode_raw<B>(&self, buf: &mut B)
+    where
+        B: BufMut,
+    {
+        self.0
+            .write_to_writer(&mut buf.writer())
+            .expect("scip documents have no required fields")
+    }
+
+    fn merge_field<B>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        _ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        B: Buf,
+    {
+        // Re-encode the single field so that rust-protobuf can merge it into
+        // the document, which has the same effect as decoding it in place.
+        let mut field = Vec::new();
+        encoding::encode_key(tag, wire_type, &mut field);
+
+        let len = match wire_type {
+            WireType::Varint => {
+                encoding::encode_varint(encoding::decode_varint(buf)?, &mut field);
+                0
+            }
+            WireType::LengthDelimited => {
+                let len = encoding::decode_varint(buf)?;
+                encoding::encode_varint(len, &mut field);
+                len as usize
+            }
+            WireType::ThirtyTwoBit => 4,
+            WireType::SixtyFourBit => 8,
+            WireType::StartGroup | WireType::EndGroup => {
+                return Err(DecodeError::new(
+                    "groups are not supported in scip documents",
+                ))
+            }
+        };
+
+        if buf.remaining() < len {
+            return Err(DecodeError::new("buffer underflow"));
+        }
+        let start = field.len();
+        field.resize(start + len, 0);
+        buf.copy_to_slice(&mut field[start..]);
+
+        self.0
+            .merge_from_bytes(&field)
+            .map_err(|err| DecodeError::new(err.to_string()))
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.0.compute_size() as usize
+    }
+
+    fn clear(&mut self) {
+        self.0.clear()
+    }
+}
+
+pub struct Service {
+    config: Config,
+    cache: Arc<Cache>,
+}
+
+#[tonic::async_trait]
+impl SyntaxHighlighter for Service {
+    async fn highlight(
+        &self,
+        request: Request<HighlightRequest>,
+    ) -> Result<Response<HighlightResponse>, Status> {
+        let request = request.into_inner();
+        let query = ScipHighlightQuery {
+            engine: request.engine().into(),
+            code: request.code,
+            filepath: request.filepath,
+            filetype: request.filetype,
+            line_length_limit: request.line_length_limit.map(|limit| limit as usize),
+            timeout_ms: self.config.timeout_ms(request.timeout_ms),
+            start_line: request.start_line.map(|line| line as usize),
+            end_line: request.end_line.map(|line| line as usize),
+        };
+        self.config
+            .check_engine(query.engine)
+            .map_err(error_to_status)?;
+
+        let language = sg_syntax::language_name(&query.filepath, query.filetype.as_deref());
+        let timer = RequestTimer::start("grpc:Highlight", query.engine.as_str(), &language);
+
+        let limits = self.config.limits;
+        let degraded = limits.scip_degradation(&query);
+        let key = crate::scip_cache_key(&query);
+        let cache = self.cache.clone();
+        let document = run_highlighter(timer, move || {
+            cache.document(key, || {
+                sg_syntax::scip_highlight_document_with_limits(query, &limits)
+            })
+        })
+        .await?;
+
+        Ok(Response::new(HighlightResponse {
+            document: Some(ScipDocument(document)),
+            plaintext: degraded == Some(Degradation::Plaintext),
+            degraded: degraded.map(|degraded| degraded.as_str().to_string()),
+        }))
+    }
+
+    async fn symbols(
+        &self,
+        request: Request<SymbolsRequest>,
+    ) -> Result<Response<SymbolsResponse>, Status> {
+        self.config
+            .check_engine(sg_syntax::SyntaxEngine::ScipSyntax)
+            .map_err(error_to_status)?;
+
+        let request = request.into_inner();
+        let language = sg_syntax::language_name(&request.filename, None);
+        let timer = RequestTimer::start("grpc:Symbols", "scip-syntax", &language);
+
+        let limits = self.config.limits;
+        let degraded = crate::symbols_degradation(&request.content, &limits);
+        let key = crate::symbols_cache_key(&request.filename, &request.content);
+        let cache = self.cache.clone();
+        let document = run_highlighter(timer, move || {
+            cache.document(key, || {
+                crate::symbols_document_with_limits(&request.filename, &request.content, &limits)
+            })
+        })
+        .await?;
+
+        Ok(Response::new(SymbolsResponse {
+            document: Some(ScipDocument(document)),
+            degraded: degraded.map(|degraded| degraded.as_str().to_string()),
+        }))
+    }
+
+    async fn ctags(
+        &self,
+        request: Request<CtagsRequest>,
+    ) -> Result<Response<CtagsResponse>, Status> {
+        self.config
+            .check_engine(sg_syntax::SyntaxEngine::ScipSyntax)
+            .map_err(error_to_status)?;
+
+        let request = request.into_inner();
+        let language = sg_syntax::language_name(&request.filename, None);
+        let timer = RequestTimer::start("grpc:Ctags", "scip-syntax", &language);
+
+        let limits = self.config.limits;
+        let tags = run_highlighter(timer, move || {
+            // Unlike highlighting, there is no cheaper way to produce tags,
+            // so files over the limits are rejected instead of degraded.
+            if let Some(reason) = limits.exceeded_by(&String::from_utf8_lossy(&request.content)) {
+                return Err(HighlightError::TooLarge(reason));
+            }
+
+            let mut tags = vec![];
+            // Files we don't have a parser for simply have no tags, which is
+            // the same thing the scip-ctags binary does.
+            for_each_tag(&request.filename, &request.content, &mut |reply| {
+                if let Reply::Tag {
+                    name,
+                    path,
+                    language,
+                    line,
+                    kind,
+                    scope,
+                } = reply
+                {
+                    tags.push(Tag {
+                        name,
+                        path: path.to_string(),
+                        language: language.to_string(),
+                        line: line as u32,
+                        kind: kind.to_string(),
+                        scope: scope.map(str::to_string),
+                    });
+                }
+            });
+            Ok(tags)
+        })
+        .await?;
+
+        Ok(Response::new(CtagsResponse { tags }))
+    }
+}
+
+/// Highlighting is CPU-bound, so keep it off of the async executor. This also
+/// turns panics into an error status instead of taking the server down, and
+/// records metrics for the request.
+async fn run_highlighter<T, F>(timer: RequestTimer, f: F) -> Result<T, Status>
+where
+    F: FnOnce() -> Result<T, HighlightError> + Send + 'static,
+    T: Send + 'static,
+{
+    let result = rocket::tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or(Err(HighlightError::Panic));
+
+    timer.finish(result.as_ref().err());
+    result.map_err(error_to_status)
+}
+
+fn error_to_status(err: HighlightError) -> Status {
+    let message = err.to_string();
+    match err {
+        HighlightError::InvalidRequest(_)
+        | HighlightError::InvalidTheme(_)
+        | HighlightError::InvalidCustomTheme(_) => Status::invalid_argument(message),
+        HighlightError::UnknownLanguage(_) | HighlightError::ParseFailed(_) => {
+            Status::failed_precondition(message)
+        }
+        HighlightError::TooLarge(_) => Status::resource_exhausted(message),
+        HighlightError::Timeout => Status::deadline_exceeded(message),
+        HighlightError::Panic | HighlightError::Internal(_) => Status::internal(message),
+    }
+}
+
+pub async fn serve(
+    addr: SocketAddr,
+    config: Config,
+    cache: Arc<Cache>,
+) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(SyntaxHighlighterServer::new(Service { config, cache }))
+        .serve(addr)
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use prost::Message as _;
+    use protobuf::{EnumOrUnknown, Message as _};
+    use scip::types::{Occurrence, SymbolInformation, SyntaxKind};
+
+    use super::*;
+
+    fn document() -> Document {
+        Document {
+            occurrences: vec![
+                Occurrence {
+                    range: vec![0, 0, 5],
+                    syntax_kind: EnumOrUnknown::new(SyntaxKind::IdentifierKeyword),
+                    ..Default::default()
+                },
+                Occurrence {
+                    range: vec![1, 4, 2, 1],
+                    symbol: "local 1".to_string(),
+                    ..Default::default()
+                },
+            ],
+            symbols: vec![SymbolInformation {
+                symbol: "local 1".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn documents_round_trip() {
+        let response = HighlightResponse {
+            document: Some(ScipDocument(document())),
+            plaintext: false,
+            degraded: Some("tree-sitter".to_string()),
+        };
+
+        let decoded = HighlightResponse::decode(response.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, response);
+    }
+
+    #[test]
+    fn documents_are_embedded_scip_documents() {
+        // The document field has to be exactly what protoc would generate for
+        // `scip.Document document = 1`, which is the encoded document behind
+        // a length-delimited key.
+        let response = SymbolsResponse {
+            document: Some(ScipDocument(document())),
+            degraded: None,
+        };
+
+        let mut expected = vec![];
+        encoding::bytes::encode(1, &document().write_to_bytes().unwrap(), &mut expected);
+        assert_eq!(response.encode_to_vec(), expected);
+        assert_eq!(response.encoded_len(), expected.len());
+    }
+}
@@ -0,0 +1,46 @@
+//! JSON log output for `log_format = "json"`.
+//!
+//! Rocket only installs its own logger if no other logger is installed yet,
+//! so installing this one before launching replaces all of Rocket's output,
+//! including its request logs.
+
+use rocket::serde::json::json;
+
+use crate::config::LogFormat;
+
+struct JsonLogger;
+
+impl log::Log for JsonLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = json!({
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "message": record.args().to_string(),
+        });
+        println!("{}", line);
+    }
+
+    fn flush(&self) {}
+}
+
+pub fn init(format: LogFormat) {
+    match format {
+        // Rocket sets up its own logger when launching.
+        LogFormat::Text => {}
+        LogFormat::Json => {
+            log::set_boxed_logger(Box::new(JsonLogger)).expect("no other logger is installed");
+            log::set_max_level(log::LevelFilter::Info);
+            // Rocket colors parts of its messages, which only makes sense in
+            // a terminal.
+            rocket::yansi::Paint::disable();
+        }
+    }
+}
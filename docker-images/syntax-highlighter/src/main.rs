@@ -3,93 +3,430 @@
 #[macro_use]
 extern crate rocket;
 
-use std::path;
+use std::{net::SocketAddr, sync::Arc};
 
 use protobuf::Message;
-use rocket::serde::json::{json, Json, Value as JsonValue};
+use rayon::prelude::*;
+use rocket::{
+    http::{Accept, ContentType},
+    response::{self, Responder},
+    serde::json::{json, Json, Value as JsonValue},
+    Request, State,
+};
+use scip::types::Document;
 use scip_syntax::get_globals;
-use scip_treesitter_languages::parsers::BundledParser;
+use scip_treesitter_languages::detection::detect_language;
 use serde::Deserialize;
-use sg_syntax::{ScipHighlightQuery, SourcegraphQuery};
+use sg_syntax::{
+    AnsiHighlightQuery, Degradation, HighlightError, Limits, ScipHighlightQuery, SourcegraphQuery,
+    SyntaxEngine,
+};
+
+mod cache;
+use cache::{Cache, KeyBuilder};
+
+mod config;
+use config::{Config, Settings};
+
+mod error;
+use error::ApiError;
+
+mod grpc;
+mod logging;
+mod metrics;
+use metrics::RequestTimer;
 
 #[post("/", format = "application/json", data = "<q>")]
-fn syntect(q: Json<SourcegraphQuery>) -> JsonValue {
+fn syntect(
+    q: Json<SourcegraphQuery>,
+    config: &State<Config>,
+    cache: &State<Arc<Cache>>,
+) -> Result<JsonValue, ApiError> {
+    config.check_engine(q.engine)?;
+    let mut q = q.into_inner();
+    q.timeout_ms = config.timeout_ms(q.timeout_ms);
+
+    let language = sg_syntax::language_name(&q.filepath, q.filetype.as_deref());
+    let timer = RequestTimer::start("/", q.engine.as_str(), &language);
+
     // TODO(slimsag): In an ideal world we wouldn't be relying on catch_unwind
     // and instead Syntect would return Result types when failures occur. This
     // will require some non-trivial work upstream:
     // https://github.com/trishume/syntect/issues/98
-    let result = std::panic::catch_unwind(|| sg_syntax::syntect_highlight(q.into_inner()));
-    match result {
-        Ok(v) => v,
-        Err(_) => json!({"error": "panic while highlighting code", "code": "panic"}),
-    }
+    let key = syntect_cache_key(&q);
+    let limits = config.limits;
+    let result = cache.json(key, || {
+        let result =
+            std::panic::catch_unwind(|| sg_syntax::syntect_highlight_with_limits(q, &limits));
+        match result {
+            Ok(result) => result,
+            Err(_) => Err(HighlightError::Panic),
+        }
+    });
+
+    timer.finish(result.as_ref().err());
+    Ok(result?)
+}
+
+fn syntect_cache_key(q: &SourcegraphQuery) -> cache::Key {
+    KeyBuilder::new("syntect")
+        .str(q.engine.as_str())
+        .str(&q.extension)
+        .str(&q.filepath)
+        .opt_str(q.filetype.as_deref())
+        .str(if q.css { "css" } else { "inline" })
+        .opt_usize(q.line_length_limit)
+        .str(&q.theme)
+        .opt_str(q.custom_theme.as_deref())
+        .opt_usize(q.start_line)
+        .opt_usize(q.end_line)
+        .str(&q.code)
+        .finish()
+}
+
+/// Same as `/`, but with ANSI escape sequences for terminals instead of HTML.
+#[post("/ansi", format = "application/json", data = "<q>")]
+fn ansi(
+    q: Json<AnsiHighlightQuery>,
+    config: &State<Config>,
+    cache: &State<Arc<Cache>>,
+) -> Result<JsonValue, ApiError> {
+    config.check_engine(q.engine)?;
+    let mut q = q.into_inner();
+    q.timeout_ms = config.timeout_ms(q.timeout_ms);
+
+    let language = sg_syntax::language_name(&q.filepath, q.filetype.as_deref());
+    let timer = RequestTimer::start("/ansi", q.engine.as_str(), &language);
+
+    let key = ansi_cache_key(&q);
+    let limits = config.limits;
+    let result = cache.json(key, || {
+        let result = std::panic::catch_unwind(|| sg_syntax::ansi_highlight_with_limits(q, &limits));
+        match result {
+            Ok(result) => result,
+            Err(_) => Err(HighlightError::Panic),
+        }
+    });
+
+    timer.finish(result.as_ref().err());
+    Ok(result?)
+}
+
+fn ansi_cache_key(q: &AnsiHighlightQuery) -> cache::Key {
+    KeyBuilder::new("ansi")
+        .str(q.engine.as_str())
+        .str(&q.filepath)
+        .opt_str(q.filetype.as_deref())
+        .str(q.colors.as_str())
+        .opt_usize(q.line_length_limit)
+        .str(&q.theme)
+        .opt_str(q.custom_theme.as_deref())
+        .str(&q.code)
+        .finish()
 }
 
 // TODO: Once we're confident we don't need this anymore, we can remove this entirely
 // and just have the `scip` endpoint. But I figured I would make it available at least
 // for now, since I'm working on doing that.
 #[post("/lsif", format = "application/json", data = "<q>")]
-fn lsif(q: Json<SourcegraphQuery>) -> JsonValue {
-    match sg_syntax::lsif_highlight(q.into_inner()) {
-        Ok(v) => v,
-        Err(err) => err,
+fn lsif(q: Json<SourcegraphQuery>, config: &State<Config>) -> Result<JsonValue, ApiError> {
+    config.check_engine(SyntaxEngine::TreeSitter)?;
+    let mut q = q.into_inner();
+    q.timeout_ms = config.timeout_ms(q.timeout_ms);
+
+    let language = sg_syntax::language_name(&q.filepath, q.filetype.as_deref());
+    let timer = RequestTimer::start("/lsif", "tree-sitter", &language);
+
+    let result = sg_syntax::lsif_highlight(q);
+    timer.finish(result.as_ref().err());
+    Ok(result?)
+}
+
+/// `/scip` and `/symbols` return the SCIP document base64-encoded inside of a
+/// JSON body by default. Clients that send `Accept: application/x-protobuf`
+/// get the encoded document as the response body instead, which avoids the
+/// base64 and JSON overhead for large documents. Errors are always JSON.
+#[derive(Responder)]
+enum ScipResponse {
+    Json(JsonValue),
+    Protobuf(ProtobufResponse),
+    Error(ApiError),
+}
+
+impl ScipResponse {
+    fn new(
+        document: Result<Document, HighlightError>,
+        degraded: Option<Degradation>,
+        accept: Option<&Accept>,
+    ) -> Self {
+        let response = if accepts_protobuf(accept) {
+            document
+                .and_then(encode_document)
+                .map(|encoded| Self::Protobuf(ProtobufResponse { encoded, degraded }))
+        } else {
+            document
+                .and_then(|document| document_to_json(document, degraded))
+                .map(Self::Json)
+        };
+
+        response.unwrap_or_else(|err| Self::Error(err.into()))
     }
 }
 
-#[post("/scip", format = "application/json", data = "<q>")]
-fn scip(q: Json<ScipHighlightQuery>) -> JsonValue {
-    match sg_syntax::scip_highlight(q.into_inner()) {
-        Ok(v) => v,
-        Err(err) => err,
+/// The encoded document. If the file was too large to be highlighted
+/// properly, the `X-Highlight-Degraded` header says how it was highlighted
+/// instead, like the `degraded` field of the JSON response.
+struct ProtobufResponse {
+    encoded: Vec<u8>,
+    degraded: Option<Degradation>,
+}
+
+impl<'r> Responder<'r, 'static> for ProtobufResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response =
+            (ContentType::new("application", "x-protobuf"), self.encoded).respond_to(request)?;
+        if let Some(degraded) = self.degraded {
+            response.set_raw_header("X-Highlight-Degraded", degraded.as_str());
+        }
+        Ok(response)
     }
 }
 
+fn accepts_protobuf(accept: Option<&Accept>) -> bool {
+    accept.map_or(false, |accept| {
+        accept
+            .media_types()
+            .any(|media_type| media_type.top() == "application" && media_type.sub() == "x-protobuf")
+    })
+}
+
+#[post("/scip", format = "application/json", data = "<q>")]
+fn scip(
+    q: Json<ScipHighlightQuery>,
+    accept: Option<&Accept>,
+    config: &State<Config>,
+    cache: &State<Arc<Cache>>,
+) -> ScipResponse {
+    let mut q = q.into_inner();
+    q.timeout_ms = config.timeout_ms(q.timeout_ms);
+
+    let timer = scip_timer("/scip", &q);
+    let limits = config.limits;
+    let degraded = limits.scip_degradation(&q);
+    let document = config.check_engine(q.engine).and_then(|()| {
+        cache.document(scip_cache_key(&q), || {
+            sg_syntax::scip_highlight_document_with_limits(q, &limits)
+        })
+    });
+    timer.finish(document.as_ref().err());
+
+    ScipResponse::new(document, degraded, accept)
+}
+
+/// Highlights every query in the batch in parallel. The response contains one
+/// entry per query, in the same order, which is either the regular `/scip`
+/// response or an error object for that file.
+#[post("/scip/batch", format = "application/json", data = "<q>")]
+async fn scip_batch(
+    q: Json<Vec<ScipHighlightQuery>>,
+    config: &State<Config>,
+    cache: &State<Arc<Cache>>,
+) -> Result<JsonValue, ApiError> {
+    let config = *config.inner();
+    let limits = config.limits;
+    let cache = Arc::clone(cache.inner());
+    run_batch(q.into_inner(), move |mut q| {
+        q.timeout_ms = config.timeout_ms(q.timeout_ms);
+        let timer = scip_timer("/scip/batch", &q);
+        let degraded = limits.scip_degradation(&q);
+
+        // A single bad file should not take down the rest of the batch.
+        let document = config.check_engine(q.engine).and_then(|()| {
+            cache.document(scip_cache_key(&q), || {
+                let result = std::panic::catch_unwind(|| {
+                    sg_syntax::scip_highlight_document_with_limits(q, &limits)
+                });
+                match result {
+                    Ok(result) => result,
+                    Err(_) => Err(HighlightError::Panic),
+                }
+            })
+        });
+
+        timer.finish(document.as_ref().err());
+        document
+            .and_then(|document| document_to_json(document, degraded))
+            .unwrap_or_else(|err| err.to_json())
+    })
+    .await
+}
+
+fn scip_cache_key(q: &ScipHighlightQuery) -> cache::Key {
+    KeyBuilder::new("scip")
+        .str(q.engine.as_str())
+        .str(&q.filepath)
+        .opt_str(q.filetype.as_deref())
+        .opt_usize(q.line_length_limit)
+        .opt_usize(q.start_line)
+        .opt_usize(q.end_line)
+        .str(&q.code)
+        .finish()
+}
+
+fn scip_timer(route: &str, q: &ScipHighlightQuery) -> RequestTimer {
+    let language = sg_syntax::language_name(&q.filepath, q.filetype.as_deref());
+    RequestTimer::start(route, q.engine.as_str(), &language)
+}
+
 #[derive(Deserialize, Default, Debug)]
 pub struct SymbolQuery {
     filename: String,
     content: String,
 }
 
-pub fn jsonify_err(e: impl ToString) -> JsonValue {
-    json!({"error": e.to_string()})
+#[post("/symbols", format = "application/json", data = "<q>")]
+fn symbols(
+    q: Json<SymbolQuery>,
+    accept: Option<&Accept>,
+    config: &State<Config>,
+    cache: &State<Arc<Cache>>,
+) -> ScipResponse {
+    let timer = symbols_timer("/symbols", &q);
+    let degraded = symbols_degradation(&q.content, &config.limits);
+    let document = config
+        .check_engine(SyntaxEngine::ScipSyntax)
+        .and_then(|()| {
+            cache.document(symbols_cache_key(&q.filename, &q.content), || {
+                symbols_document_with_limits(&q.filename, &q.content, &config.limits)
+            })
+        });
+    timer.finish(document.as_ref().err());
+
+    ScipResponse::new(document, degraded, accept)
 }
 
-#[post("/symbols", format = "application/json", data = "<q>")]
-fn symbols(q: Json<SymbolQuery>) -> JsonValue {
-    let path = path::Path::new(&q.filename);
-    let extension = match match path.extension() {
-        Some(vals) => vals,
-        None => {
-            return json!({"error": "Extensionless file"});
-        }
+/// Same as `/scip/batch`, but for `/symbols`.
+#[post("/symbols/batch", format = "application/json", data = "<q>")]
+async fn symbols_batch(
+    q: Json<Vec<SymbolQuery>>,
+    config: &State<Config>,
+    cache: &State<Arc<Cache>>,
+) -> Result<JsonValue, ApiError> {
+    let config = *config.inner();
+    let limits = config.limits;
+    let cache = Arc::clone(cache.inner());
+    run_batch(q.into_inner(), move |q| {
+        let timer = symbols_timer("/symbols/batch", &q);
+        let degraded = symbols_degradation(&q.content, &limits);
+        let document = config
+            .check_engine(SyntaxEngine::ScipSyntax)
+            .and_then(|()| {
+                cache.document(symbols_cache_key(&q.filename, &q.content), || {
+                    let result = std::panic::catch_unwind(|| {
+                        symbols_document_with_limits(&q.filename, &q.content, &limits)
+                    });
+                    match result {
+                        Ok(result) => result,
+                        Err(_) => Err(HighlightError::Panic),
+                    }
+                })
+            });
+
+        timer.finish(document.as_ref().err());
+        document
+            .and_then(|document| document_to_json(document, degraded))
+            .unwrap_or_else(|err| err.to_json())
+    })
+    .await
+}
+
+/// Runs `highlight` on every query of a batch in parallel. The batch waits for
+/// rayon on a blocking thread, so the async workers go on serving other
+/// requests meanwhile.
+async fn run_batch<Q, F>(queries: Vec<Q>, highlight: F) -> Result<JsonValue, ApiError>
+where
+    Q: Send + 'static,
+    F: Fn(Q) -> JsonValue + Send + Sync + 'static,
+{
+    let results = rocket::tokio::task::spawn_blocking(move || {
+        queries.into_par_iter().map(highlight).collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|_| HighlightError::Panic)?;
+
+    Ok(json!({ "results": results }))
+}
+
+fn symbols_timer(route: &str, q: &SymbolQuery) -> RequestTimer {
+    RequestTimer::start(
+        route,
+        "scip-syntax",
+        &sg_syntax::language_name(&q.filename, None),
+    )
+}
+
+fn symbols_cache_key(filename: &str, content: &str) -> cache::Key {
+    KeyBuilder::new("symbols")
+        .str(filename)
+        .str(content)
+        .finish()
+}
+
+/// The JSON response for `/scip` and `/symbols`.
+fn document_to_json(
+    document: Document,
+    degraded: Option<Degradation>,
+) -> Result<JsonValue, HighlightError> {
+    let encoded = encode_document(document)?;
+
+    let mut response = json!({
+        "scip": base64::encode(encoded),
+        "plaintext": degraded == Some(Degradation::Plaintext),
+    });
+    if let Some(degraded) = degraded {
+        response["degraded"] = json!(degraded.as_str());
     }
-    .to_str()
-    {
-        Some(vals) => vals,
-        None => {
-            return json!({"error": "Invalid codepoint"});
-        }
-    };
-    let parser = match BundledParser::get_parser_from_extension(extension) {
-        Some(parser) => parser,
-        None => return json!({"error": "Could not infer parser from extension"}),
-    };
+    Ok(response)
+}
 
-    let document = match scip_syntax::get_symbols(&parser, q.content.as_bytes()) {
-        Ok(vals) => vals,
-        Err(err) => {
-            return jsonify_err(err);
-        }
-    };
+fn encode_document(document: Document) -> Result<Vec<u8>, HighlightError> {
+    document
+        .write_to_bytes()
+        .map_err(|err| HighlightError::Internal(err.to_string()))
+}
 
-    let encoded = match document.write_to_bytes() {
-        Ok(vals) => vals,
-        Err(err) => {
-            return jsonify_err(err);
-        }
-    };
+/// Symbols are only ever generated with tree-sitter, so files over the limits
+/// simply get no symbols.
+pub(crate) fn symbols_degradation(content: &str, limits: &Limits) -> Option<Degradation> {
+    limits.exceeded_by(content).map(|_| Degradation::Plaintext)
+}
+
+pub(crate) fn symbols_document_with_limits(
+    filename: &str,
+    content: &str,
+    limits: &Limits,
+) -> Result<Document, HighlightError> {
+    match symbols_degradation(content, limits) {
+        Some(_) => Ok(Document::default()),
+        None => symbols_document(filename, content),
+    }
+}
+
+fn symbols_document(filename: &str, content: &str) -> Result<Document, HighlightError> {
+    let parser = detect_language(filename, content.as_bytes())
+        .ok_or_else(|| {
+            HighlightError::UnknownLanguage("Could not detect the language of the file".to_string())
+        })?
+        .parser;
+
+    scip_syntax::get_symbols(&parser, content.as_bytes())
+        .map_err(|err| HighlightError::ParseFailed(err.to_string()))
+}
 
-    json!({"scip": base64::encode(encoded), "plaintext": false})
+/// The languages the server knows about, see `sg_syntax::list_languages`.
+#[get("/languages")]
+fn languages() -> JsonValue {
+    sg_syntax::list_languages()
 }
 
 #[get("/health")]
@@ -97,13 +434,40 @@ fn health() -> &'static str {
     "OK"
 }
 
+#[get("/metrics")]
+fn prometheus_metrics() -> (ContentType, String) {
+    (ContentType::Plain, metrics::gather())
+}
+
+/// Hit rate and size of the result cache, see `cache_size_bytes`.
+#[get("/cache/stats")]
+fn cache_stats(cache: &State<Arc<Cache>>) -> JsonValue {
+    cache.stats()
+}
+
+#[catch(400)]
+fn bad_request() -> JsonValue {
+    json!({"error": "bad request", "code": "invalid_request"})
+}
+
 #[catch(404)]
 fn not_found() -> JsonValue {
     json!({"error": "resource not found", "code": "resource_not_found"})
 }
 
-#[launch]
-fn rocket() -> _ {
+// Rocket responds with this when the request body isn't a valid query.
+#[catch(422)]
+fn unprocessable_entity() -> JsonValue {
+    json!({"error": "invalid request body", "code": "invalid_request"})
+}
+
+#[catch(500)]
+fn internal_error() -> JsonValue {
+    json!({"error": "internal server error", "code": "internal"})
+}
+
+#[rocket::main]
+async fn main() -> Result<(), rocket::Error> {
     // Exits with a code zero if the environment variable SANITY_CHECK equals
     // to "true". This enables testing that the current program is in a runnable
     // state against the platform it's being executed on.
@@ -117,19 +481,62 @@ fn rocket() -> _ {
         _ => {}
     };
 
-    // load configurations on-startup instead of on-first-request.
-    // TODO: load individual languages lazily on-request instead, currently
-    // CONFIGURATIONS.get will load every configured configuration together.
-    scip_treesitter_languages::highlights::CONFIGURATIONS
-        .get(&scip_treesitter_languages::parsers::BundledParser::Go);
+    let settings = Settings::load();
+    logging::init(settings.log_format);
 
-    // Only list features if QUIET != "true"
-    match std::env::var("QUIET") {
-        Ok(v) if v == "true" => {}
-        _ => sg_syntax::list_features(),
-    };
+    // Tree-sitter languages are loaded on their first request, except for
+    // these, so that the first requests for common languages aren't slow.
+    for language in &settings.preload_languages {
+        scip_treesitter_languages::highlights::get_highlighting_configuration(language);
+    }
+
+    if !settings.quiet {
+        sg_syntax::list_features();
+    }
+
+    let config = settings.config();
+    let cache = Arc::new(Cache::new(settings.cache_size_bytes));
+
+    // The gRPC server is opt-in: http-server-stabilizer runs several copies of
+    // syntect_server side by side and only proxies HTTP traffic to them, so
+    // they can't all listen on the same gRPC port.
+    if let Some(port) = settings.grpc_port {
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+
+        let cache = cache.clone();
+        rocket::tokio::spawn(async move {
+            if let Err(err) = grpc::serve(addr, config, cache).await {
+                log::error!("gRPC server stopped: {}", err);
+            }
+        });
+    }
+
+    let _ = rocket(&settings, config, cache).launch().await?;
+    Ok(())
+}
 
-    rocket::build()
-        .mount("/", routes![syntect, lsif, scip, symbols, health])
-        .register("/", catchers![not_found])
+fn rocket(settings: &Settings, config: Config, cache: Arc<Cache>) -> rocket::Rocket<rocket::Build> {
+    rocket::custom(settings.figment())
+        .manage(config)
+        .manage(cache)
+        .mount(
+            "/",
+            routes![
+                syntect,
+                ansi,
+                lsif,
+                scip,
+                scip_batch,
+                symbols,
+                symbols_batch,
+                languages,
+                health,
+                prometheus_metrics,
+                cache_stats
+            ],
+        )
+        .register(
+            "/",
+            catchers![bad_request, not_found, unprocessable_entity, internal_error],
+        )
 }
@@ -0,0 +1,97 @@
+//! Prometheus metrics, served from `/metrics`.
+
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter_vec, Encoder, HistogramVec,
+    IntCounterVec, TextEncoder,
+};
+use sg_syntax::HighlightError;
+
+lazy_static! {
+    static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "syntax_highlighter_requests_total",
+        "Number of highlighting requests handled.",
+        &["route", "engine", "language"]
+    )
+    .unwrap();
+    static ref REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "syntax_highlighter_request_duration_seconds",
+        "Time spent handling a highlighting request.",
+        &["route", "engine", "language"],
+        // 5ms up to ~40s
+        exponential_buckets(0.005, 2.0, 14).unwrap()
+    )
+    .unwrap();
+    static ref PANICS: IntCounterVec = register_int_counter_vec!(
+        "syntax_highlighter_panics_total",
+        "Number of panics caught while handling a request.",
+        &["route", "engine", "language"]
+    )
+    .unwrap();
+    static ref UNKNOWN_LANGUAGES: IntCounterVec = register_int_counter_vec!(
+        "syntax_highlighter_unknown_language_total",
+        "Number of requests for a language the engine does not support.",
+        &["route", "engine", "language"]
+    )
+    .unwrap();
+    static ref TIMEOUTS: IntCounterVec = register_int_counter_vec!(
+        "syntax_highlighter_timeouts_total",
+        "Number of requests that gave up because they took too long.",
+        &["route", "engine", "language"]
+    )
+    .unwrap();
+}
+
+/// Tracks a single request (or a single file of a batch request) from start
+/// to finish.
+///
+/// `language` must come from a fixed set of names, such as the ones returned
+/// by `sg_syntax::language_name`, since every distinct value creates a new
+/// time series.
+pub struct RequestTimer {
+    labels: [String; 3],
+    start: Instant,
+}
+
+impl RequestTimer {
+    pub fn start(route: &str, engine: &str, language: &str) -> Self {
+        Self {
+            labels: [route.to_string(), engine.to_string(), language.to_string()],
+            start: Instant::now(),
+        }
+    }
+
+    /// Records the request, with `error` being the error returned to the
+    /// client, if any.
+    pub fn finish(self, error: Option<&HighlightError>) {
+        let labels = self.labels();
+        REQUESTS.with_label_values(&labels).inc();
+        REQUEST_DURATION
+            .with_label_values(&labels)
+            .observe(self.start.elapsed().as_secs_f64());
+
+        match error {
+            Some(HighlightError::Panic) => PANICS.with_label_values(&labels).inc(),
+            Some(HighlightError::UnknownLanguage(_)) => {
+                UNKNOWN_LANGUAGES.with_label_values(&labels).inc()
+            }
+            Some(HighlightError::Timeout) => TIMEOUTS.with_label_values(&labels).inc(),
+            _ => {}
+        }
+    }
+
+    fn labels(&self) -> [&str; 3] {
+        [&self.labels[0], &self.labels[1], &self.labels[2]]
+    }
+}
+
+/// Renders every registered metric in the Prometheus text format.
+pub fn gather() -> String {
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("metrics are valid");
+    String::from_utf8(buffer).expect("metrics are valid utf-8")
+}